@@ -0,0 +1,184 @@
+// The ways a board can be solved, behind one trait so that the benchmark, the cross-check and the
+// game can pick a strategy by name and compare their answers.
+
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::best_first::BestFirst;
+use crate::board::Board;
+use crate::cnf;
+use crate::heuristic::Heuristic;
+use crate::parallel;
+
+///How much work a solver may do before giving up
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub steps: u64,       // SolverStack steps, summed over threads
+    pub conflicts: u64,   // SAT conflicts
+    pub frontier: usize,  // States held at once by best-first search
+    pub control: Control, // Lets another thread stop the solve early
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            steps: 1_000_000,
+            conflicts: 1_000_000,
+            frontier: 50_000,
+            control: Control::default(),
+        }
+    }
+}
+
+///Cancels a running solve and hears how far it has got. Clones share the same flag and callback.
+#[derive(Clone, Default)]
+pub struct Control {
+    cancelled: Arc<AtomicBool>,
+    progress: Option<Arc<dyn Fn(u64) + Send + Sync>>,
+}
+
+impl Control {
+    ///Calls `f` now and then with the work done so far, in the solver's own unit (steps,
+    ///expansions or conflicts)
+    pub fn on_progress(mut self, f: impl Fn(u64) + Send + Sync + 'static) -> Control {
+        self.progress = Some(Arc::new(f));
+        self
+    }
+
+    ///Asks the solve to stop. It gives up at its next check, which is soon but not immediate.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn report(&self, work: u64) {
+        if let Some(progress) = &self.progress {
+            progress(work);
+        }
+    }
+}
+
+impl fmt::Debug for Control {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Control")
+            .field("cancelled", &self.is_cancelled())
+            .finish_non_exhaustive()
+    }
+}
+
+pub struct SolveOutcome {
+    pub solution: Option<Board>,
+    pub gave_up: bool, // Hit a limit or was cancelled before finding a solution or showing there is none
+    pub counts: Vec<(&'static str, u64)>, // Solver-specific counters, e.g. ("steps", 1234)
+    pub time: Duration,
+}
+
+impl SolveOutcome {
+    pub fn verdict(&self) -> &'static str {
+        if self.solution.is_some() {
+            "solved"
+        } else if self.gave_up {
+            "GAVE UP"
+        } else {
+            "FAILED"
+        }
+    }
+}
+
+pub trait PuzzleSolver: Send {
+    ///Name and settings, for reports
+    fn describe(&self) -> String;
+
+    fn solve(&mut self, board: &Board, limits: &Limits) -> SolveOutcome;
+}
+
+pub const NAMES: [&str; 3] = ["search", "bestfirst", "sat"];
+
+///A solver by name. The heuristic only matters to the searches, and the thread count to search.
+pub fn by_name(name: &str, heuristic: Heuristic, threads: usize) -> Option<Box<dyn PuzzleSolver>> {
+    match name {
+        "search" => Some(Box::new(Search { heuristic, threads })),
+        "bestfirst" => Some(Box::new(BestFirst { heuristic })),
+        "sat" => Some(Box::new(Sat)),
+        _ => None,
+    }
+}
+
+///Finishes the board in a file, keeping any paths already drawn on it, and prints the result in
+///its text format
+pub fn solve_file(path: &str, solver: &str, heuristic: Heuristic) {
+    let board = Board::parse(&fs::read_to_string(path).expect("Couldn't read the board file"));
+    let mut solver = by_name(solver, heuristic, 1).expect("Unknown solver");
+    let outcome = solver.solve(&board, &Limits::default());
+    match &outcome.solution {
+        Some(solution) => print!("{}", solution.to_text()),
+        None => print!("{}", board.to_text()),
+    }
+    println!(
+        "{} in {:.2?} ({})",
+        outcome.verdict(),
+        outcome.time,
+        solver.describe()
+    );
+}
+
+///Depth-first search with SolverStack, spread over `threads` threads
+pub struct Search {
+    pub heuristic: Heuristic,
+    pub threads: usize,
+}
+
+impl PuzzleSolver for Search {
+    fn describe(&self) -> String {
+        format!(
+            "search, {} heuristic, {} threads",
+            self.heuristic.name(),
+            self.threads
+        )
+    }
+
+    fn solve(&mut self, board: &Board, limits: &Limits) -> SolveOutcome {
+        let report = parallel::solve(board, self.heuristic, self.threads, limits);
+        SolveOutcome {
+            solution: report.solution,
+            gave_up: report.gave_up,
+            counts: vec![
+                ("steps", report.steps),
+                ("branches", report.branches),
+                ("table lookups", report.lookups),
+                ("table hits", report.hits),
+            ],
+            time: report.time,
+        }
+    }
+}
+
+///CNF encoding handed to the built-in SAT solver
+pub struct Sat;
+
+impl PuzzleSolver for Sat {
+    fn describe(&self) -> String {
+        "sat".to_string()
+    }
+
+    fn solve(&mut self, board: &Board, limits: &Limits) -> SolveOutcome {
+        let start = Instant::now();
+        let (solution, stats) = cnf::solve(board, limits.conflicts, &limits.control);
+        SolveOutcome {
+            solution,
+            gave_up: stats.gave_up,
+            counts: vec![
+                ("conflicts", stats.conflicts),
+                ("decisions", stats.decisions),
+                ("loops", stats.loops as u64),
+            ],
+            time: start.elapsed(),
+        }
+    }
+}
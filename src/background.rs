@@ -0,0 +1,117 @@
+// Solver runs for the game, on worker threads so the event loop keeps drawing. Each run gets a
+// Control to cancel it by, and its progress and outcome come back to the event loop as user events
+// through winit's EventLoopProxy.
+//
+// There is at most one run per task. Starting another cancels the old one, and any events the old
+// one still sends are recognised by their id and dropped.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use winit::event_loop::EventLoopProxy;
+
+use crate::backend::{Control, Limits, PuzzleSolver, SolveOutcome};
+use crate::board::Board;
+
+const PROGRESS_EVERY: Duration = Duration::from_millis(100); // Keeps progress from flooding the loop
+
+// Ids are unique across every Background, so a run left over from the last level can't be taken
+// for one of this level's
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+///What a run is for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Task {
+    Check, // Can the player's position still be finished?
+    Solve, // A solution to show the player
+}
+
+pub enum SolverEvent {
+    Progress {
+        task: Task,
+        id: u64,
+        work: u64,
+    },
+    Done {
+        task: Task,
+        id: u64,
+        outcome: SolveOutcome,
+    },
+}
+
+pub struct Background {
+    proxy: EventLoopProxy<SolverEvent>,
+    runs: Vec<(Task, u64, Control)>,
+}
+
+impl Background {
+    pub fn new(proxy: EventLoopProxy<SolverEvent>) -> Background {
+        Background {
+            proxy,
+            runs: Vec::new(),
+        }
+    }
+
+    ///Solves `board` on a new thread, cancelling any earlier run for the same task
+    pub fn start(&mut self, task: Task, board: Board, mut solver: Box<dyn PuzzleSolver>) {
+        self.cancel(task);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let proxy = self.proxy.clone();
+        let last = Mutex::new(Instant::now());
+        let control = Control::default().on_progress(move |work| {
+            let mut last = last.lock().unwrap();
+            if last.elapsed() >= PROGRESS_EVERY {
+                *last = Instant::now();
+                let _ = proxy.send_event(SolverEvent::Progress { task, id, work });
+            }
+        });
+        let limits = Limits {
+            control: control.clone(),
+            ..Limits::default()
+        };
+        let proxy = self.proxy.clone();
+        thread::spawn(move || {
+            let outcome = solver.solve(&board, &limits);
+            // Fails only once the event loop has closed, when nobody is waiting for the answer
+            let _ = proxy.send_event(SolverEvent::Done { task, id, outcome });
+        });
+        self.runs.push((task, id, control));
+    }
+
+    pub fn cancel(&mut self, task: Task) {
+        self.runs.retain(|(t, _, control)| {
+            if *t == task {
+                control.cancel();
+            }
+            *t != task
+        });
+    }
+
+    pub fn is_running(&self, task: Task) -> bool {
+        self.runs.iter().any(|(t, _, _)| *t == task)
+    }
+
+    ///True if the event comes from the current run for its task. A Done event ends that run.
+    pub fn accept(&mut self, event: &SolverEvent) -> bool {
+        let (task, id) = match event {
+            SolverEvent::Progress { task, id, .. } | SolverEvent::Done { task, id, .. } => {
+                (*task, *id)
+            }
+        };
+        let current = self.runs.iter().any(|(t, i, _)| (*t, *i) == (task, id));
+        if current && let SolverEvent::Done { .. } = event {
+            self.runs.retain(|(_, i, _)| *i != id);
+        }
+        current
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        for (_, _, control) in &self.runs {
+            control.cancel();
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// Benchmark: runs a solver over every puzzle of one size in flows/ and reports the solve rate,
+// the solver's own counters (steps, conflicts, ...) and the time taken. Several puzzles can be
+// solved at once, and a search can be split across several threads. Puzzles still unsolved at the
+// default Limits are given up on so one hard board can't stall the whole pack.
+//
+// check runs every solver on each puzzle instead, and reports puzzles where they disagree.
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use crate::backend::{self, Limits, PuzzleSolver};
+use crate::board::Board;
+use crate::heuristic::Heuristic;
+use crate::parallel;
+
+///Every size with puzzles in flows/, smallest first
+pub fn sizes() -> Vec<String> {
+    let mut sizes: Vec<(usize, usize, String)> = fs::read_dir("flows")
+        .unwrap()
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let size = name.split_once('_')?.0.to_string();
+            let (cols, rows) = size.split_once('x')?;
+            Some((cols.parse().ok()?, rows.parse().ok()?, size))
+        })
+        .collect();
+    sizes.sort_by_key(|(cols, rows, _)| (cols * rows, *cols));
+    sizes.dedup();
+    sizes.into_iter().map(|(_, _, size)| size).collect()
+}
+
+///Every puzzle of the given size ("15x15", cols x rows) in flows/, ordered by number
+pub fn pack(size: &str) -> Vec<(u32, Board)> {
+    let (cols, rows) = size
+        .split_once('x')
+        .and_then(|(c, r)| Some((c.parse().ok()?, r.parse().ok()?)))
+        .expect("Size should look like 15x15");
+    let prefix = format!("{}_", size);
+
+    let mut puzzles: Vec<(u32, Board)> = fs::read_dir("flows")
+        .unwrap()
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let n = name
+                .strip_prefix(&prefix)?
+                .strip_suffix(".txt")?
+                .parse()
+                .ok()?;
+            let mut board = Board::load_board(
+                &fs::read_to_string(format!("flows/{}", name)).ok()?,
+                rows,
+                cols,
+            );
+            board.strip();
+            Some((n, board))
+        })
+        .collect();
+    puzzles.sort_by_key(|(n, _)| *n);
+    puzzles
+}
+
+pub fn run(
+    size: &str,
+    count: usize,
+    solver: &str,
+    heuristic: Heuristic,
+    threads: usize,
+    workers: usize,
+) {
+    let (numbers, boards): (Vec<u32>, Vec<Board>) = pack(size).into_iter().take(count).unzip();
+    let make = || backend::by_name(solver, heuristic, threads).unwrap();
+    assert!(backend::NAMES.contains(&solver), "Unknown solver");
+    let mut solved = 0;
+    let mut totals: Vec<(&'static str, u64)> = Vec::new();
+
+    let start = Instant::now();
+    parallel::solve_all(&boards, workers, make, &Limits::default(), |i, outcome| {
+        println!(
+            "{}_{}: {} in {}, {:.2?}",
+            size,
+            numbers[i],
+            outcome.verdict(),
+            counts(&outcome.counts),
+            outcome.time
+        );
+        solved += outcome.solution.is_some() as usize;
+        for (name, count) in outcome.counts {
+            match totals.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, total)) => *total += count,
+                None => totals.push((name, count)),
+            }
+        }
+    });
+
+    println!(
+        "{}: solved {}/{} in {:.2?} ({}; {}, {} workers)",
+        size,
+        solved,
+        boards.len(),
+        start.elapsed(),
+        counts(&totals),
+        make().describe(),
+        workers
+    );
+    let total = |name| totals.iter().find(|(n, _)| *n == name).map(|(_, c)| *c);
+    if let (Some(lookups), Some(hits)) = (total("table lookups"), total("table hits")) {
+        println!(
+            "Transposition table: {} hits in {} lookups ({:.1}%)",
+            hits,
+            lookups,
+            100.0 * hits as f64 / lookups.max(1) as f64
+        );
+    }
+}
+
+// "12 steps, 3 branches"
+fn counts(counts: &[(&str, u64)]) -> String {
+    let parts: Vec<String> = counts
+        .iter()
+        .map(|(name, count)| format!("{} {}", count, name))
+        .collect();
+    parts.join(", ")
+}
+
+///Solves the first `count` puzzles of a size ("all" for every size) with every solver, and
+///reports any puzzle where they come back with different boards or one of them has no answer
+pub fn check(size: &str, count: usize, heuristic: Heuristic) {
+    let sizes = if size == "all" {
+        sizes()
+    } else {
+        vec![size.to_string()]
+    };
+    let mut solvers: Vec<Box<dyn PuzzleSolver>> = backend::NAMES
+        .iter()
+        .map(|name| backend::by_name(name, heuristic, 1).unwrap())
+        .collect();
+    let limits = Limits::default();
+    let mut checked = 0;
+    let mut disagreements = 0;
+    let mut unsolved = 0;
+    let mut times = vec![Duration::ZERO; solvers.len()];
+
+    for size in sizes {
+        for (n, board) in pack(&size).into_iter().take(count) {
+            let solutions: Vec<Option<Board>> = solvers
+                .iter_mut()
+                .zip(&mut times)
+                .map(|(solver, time)| {
+                    let outcome = solver.solve(&board, &limits);
+                    *time += outcome.time;
+                    outcome
+                        .solution
+                        .filter(|s| s.is_solved() && s.paths().is_ok() && keeps_heads(&board, s))
+                })
+                .collect();
+
+            checked += 1;
+            let found: Vec<&Board> = solutions.iter().flatten().collect();
+            if found.windows(2).any(|w| w[0] != w[1]) {
+                disagreements += 1;
+                println!("{}_{}: DISAGREE", size, n);
+            }
+            if found.len() < solutions.len() {
+                unsolved += 1;
+                let missing: Vec<&str> = backend::NAMES
+                    .iter()
+                    .zip(&solutions)
+                    .filter(|(_, s)| s.is_none())
+                    .map(|(name, _)| *name)
+                    .collect();
+                println!("{}_{}: not solved by {}", size, n, missing.join(", "));
+            }
+        }
+        println!("{}: checked", size);
+    }
+
+    let timings: Vec<String> = backend::NAMES
+        .iter()
+        .zip(times)
+        .map(|(name, time)| format!("{} {:.2?}", name, time))
+        .collect();
+    println!(
+        "{} puzzles, {} disagreements, {} not solved by every solver ({})",
+        checked,
+        disagreements,
+        unsolved,
+        timings.join(", ")
+    );
+}
+
+// True if a solution has the same heads as the puzzle it came from
+fn keeps_heads(puzzle: &Board, solution: &Board) -> bool {
+    puzzle
+        .iter()
+        .zip(solution.iter())
+        .all(|(p, s)| !p.is_head() || p == s)
+}
@@ -0,0 +1,157 @@
+// Best-first search, as in mzucker's solver: instead of always going deeper like SolverStack, keep
+// every open state in a heap and expand the cheapest one next. A state is only pushed once its
+// forced moves are made and it has survived the same pruning as the depth-first search.
+//
+// Each state in the heap is a whole Solver, so the heap is capped (Limits::frontier) and the search
+// gives up when it fills.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::time::Instant;
+
+use crate::backend::{Limits, PuzzleSolver, SolveOutcome};
+use crate::board::Board;
+use crate::heuristic::Heuristic;
+use crate::solver::Solver;
+
+pub struct BestFirst {
+    pub heuristic: Heuristic, // Picks the tip to branch on and the order of its moves
+}
+
+// A state waiting in the frontier. `order` breaks ties in favour of the older state, so that runs
+// are repeatable.
+struct Node {
+    cost: usize,
+    order: u64,
+    solver: Solver,
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Node {}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// BinaryHeap pops the greatest, so the cheapest node compares greatest
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.cost, other.order).cmp(&(self.cost, self.order))
+    }
+}
+
+enum Settled {
+    Solved,
+    Dead,
+    Open,
+}
+
+// Makes forced moves until the state needs a guess, is solved, or can be seen to have no solution
+fn settle(solver: &mut Solver) -> Settled {
+    loop {
+        if solver.get_board().is_solved() {
+            return Settled::Solved;
+        }
+        if solver.failed() {
+            return Settled::Dead;
+        }
+        if !solver.timid_step() {
+            break;
+        }
+    }
+    if solver.bottleneck() {
+        Settled::Dead
+    } else {
+        Settled::Open
+    }
+}
+
+// Lower is more promising. Every empty cell still has to be filled, every guess so far is a chance
+// of having gone wrong, and every separate region or tip with three ways to go is one more thing
+// left to get right.
+fn cost(solver: &Solver) -> usize {
+    let free_tips = solver.tip_moves().iter().filter(|m| **m >= 3).count();
+    2 * solver.empty_cells() + solver.guesses() + solver.empty_regions() + free_tips
+}
+
+impl PuzzleSolver for BestFirst {
+    fn describe(&self) -> String {
+        format!("best-first, {} heuristic", self.heuristic.name())
+    }
+
+    fn solve(&mut self, board: &Board, limits: &Limits) -> SolveOutcome {
+        let start = Instant::now();
+        let mut frontier = BinaryHeap::new();
+        let (mut expanded, mut pushed, mut pruned, mut peak) = (0, 0, 0, 0);
+        let mut solution = None;
+        let mut gave_up = false;
+
+        let root = Solver::from_progress(board).map(|s| s.with_heuristic(self.heuristic));
+        if let Some(mut root) = root {
+            match settle(&mut root) {
+                Settled::Solved => solution = Some(root.get_board().clone()),
+                Settled::Dead => {}
+                Settled::Open => frontier.push(Node {
+                    cost: cost(&root),
+                    order: 0,
+                    solver: root,
+                }),
+            }
+        }
+
+        'search: while let Some(Node { solver, .. }) = frontier.pop() {
+            if expanded >= limits.steps || limits.control.is_cancelled() {
+                gave_up = true;
+                break;
+            }
+            expanded += 1;
+            limits.control.report(expanded);
+            let Some(moves) = solver.branch_step() else {
+                continue;
+            };
+            for m in moves {
+                let mut child = solver.clone();
+                child.make(m, true);
+                match settle(&mut child) {
+                    Settled::Solved => {
+                        solution = Some(child.get_board().clone());
+                        break 'search;
+                    }
+                    Settled::Dead => pruned += 1,
+                    Settled::Open => {
+                        if frontier.len() >= limits.frontier {
+                            gave_up = true;
+                            break 'search;
+                        }
+                        pushed += 1;
+                        frontier.push(Node {
+                            cost: cost(&child),
+                            order: pushed,
+                            solver: child,
+                        });
+                    }
+                }
+            }
+            peak = peak.max(frontier.len() as u64);
+        }
+
+        SolveOutcome {
+            solution,
+            gave_up,
+            counts: vec![
+                ("expanded", expanded),
+                ("pushed", pushed),
+                ("pruned", pruned),
+                ("peak frontier", peak),
+            ],
+            time: start.elapsed(),
+        }
+    }
+}
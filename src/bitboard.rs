@@ -0,0 +1,401 @@
+// Bitset view of a board for the solver: a mask of filled cells, one mask per colour and a mask of
+// heads. Neighbour queries and flood fills work on whole sets of cells at once by shifting.
+//
+// Cells are numbered row by row like Board, but with one spare column at the end of each row that
+// is never set, so shifting left or right can't carry a cell over into the next row. Boards up to
+// 15x15 fit in four words kept inline; bigger ones get as many words as they need on the heap.
+
+use std::fmt::Debug;
+use std::ops::{BitAndAssign, BitOrAssign};
+
+use crate::board::{Board, Cell};
+use crate::solver::Coord;
+
+const INLINE: usize = 4; // Words kept inline, enough for boards up to 15x15
+
+///Storage for the words of a set of cells. A fixed array is copied rather than allocated and has a
+///length the compiler knows, so the loops over it unroll.
+pub trait Words: Clone + Debug + Eq + AsRef<[u64]> + AsMut<[u64]> {
+    fn zeroed(words: usize) -> Self;
+}
+
+impl Words for [u64; INLINE] {
+    fn zeroed(_: usize) -> Self {
+        [0; INLINE]
+    }
+}
+
+impl Words for Vec<u64> {
+    fn zeroed(words: usize) -> Self {
+        vec![0; words]
+    }
+}
+
+///A set of cells. The sets of one board all have the same number of words.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bits<W>(W);
+
+impl<W: Words> Bits<W> {
+    ///Room for `len` bits, all clear
+    pub fn new(len: usize) -> Bits<W> {
+        Bits(W::zeroed(len.div_ceil(64)))
+    }
+
+    fn words(&self) -> &[u64] {
+        self.0.as_ref()
+    }
+
+    fn words_mut(&mut self) -> &mut [u64] {
+        self.0.as_mut()
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        self.words()[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    pub fn set(&mut self, i: usize) {
+        self.words_mut()[i / 64] |= 1 << (i % 64);
+    }
+
+    pub fn clear(&mut self, i: usize) {
+        self.words_mut()[i / 64] &= !(1 << (i % 64));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words().iter().all(|w| *w == 0)
+    }
+
+    pub fn intersects(&self, other: &Bits<W>) -> bool {
+        self.words()
+            .iter()
+            .zip(other.words())
+            .any(|(a, b)| a & b != 0)
+    }
+
+    pub fn count(&self) -> usize {
+        self.words().iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    ///Lowest set bit
+    pub fn first(&self) -> Option<usize> {
+        let words = self.words();
+        words
+            .iter()
+            .position(|w| *w != 0)
+            .map(|i| i * 64 + words[i].trailing_zeros() as usize)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words().iter().enumerate().flat_map(|(i, word)| {
+            let mut left = *word;
+            std::iter::from_fn(move || {
+                let bit = (left != 0).then(|| i * 64 + left.trailing_zeros() as usize)?;
+                left &= left - 1;
+                Some(bit)
+            })
+        })
+    }
+
+    ///Clears every cell that is in `other`
+    pub fn remove(&mut self, other: &Bits<W>) {
+        for (a, b) in self.words_mut().iter_mut().zip(other.words()) {
+            *a &= !b;
+        }
+    }
+
+    // Adds every bit of `from` moved up by n, dropping the ones that fall off the end
+    #[inline(always)]
+    fn or_shl(&mut self, from: &Bits<W>, n: usize) {
+        let (out, from) = (self.words_mut(), from.words());
+        if n < 64 {
+            // Every shift on boards up to 63 columns, in a loop of fixed length
+            for i in 0..out.len() {
+                out[i] |= from[i] << n;
+                if i > 0 {
+                    out[i] |= from[i - 1] >> 1 >> (63 - n);
+                }
+            }
+            return;
+        }
+        let (skip, n) = (n / 64, n % 64);
+        for i in skip..out.len() {
+            out[i] |= from[i - skip] << n;
+            if n > 0 && i > skip {
+                out[i] |= from[i - skip - 1] >> (64 - n);
+            }
+        }
+    }
+
+    // Adds every bit of `from` moved down by n
+    #[inline(always)]
+    fn or_shr(&mut self, from: &Bits<W>, n: usize) {
+        let (out, from) = (self.words_mut(), from.words());
+        if n < 64 {
+            for i in 0..out.len() {
+                out[i] |= from[i] >> n;
+                if i + 1 < out.len() {
+                    out[i] |= from[i + 1] << 1 << (63 - n);
+                }
+            }
+            return;
+        }
+        let (skip, n) = (n / 64, n % 64);
+        for i in 0..out.len() - skip {
+            out[i] |= from[i + skip] >> n;
+            if n > 0 && i + skip + 1 < from.len() {
+                out[i] |= from[i + skip + 1] << (64 - n);
+            }
+        }
+    }
+}
+
+impl<W: Words> BitAndAssign<&Bits<W>> for Bits<W> {
+    fn bitand_assign(&mut self, rhs: &Bits<W>) {
+        for (a, b) in self.words_mut().iter_mut().zip(rhs.words()) {
+            *a &= b;
+        }
+    }
+}
+
+impl<W: Words> BitOrAssign<&Bits<W>> for Bits<W> {
+    fn bitor_assign(&mut self, rhs: &Bits<W>) {
+        for (a, b) in self.words_mut().iter_mut().zip(rhs.words()) {
+            *a |= b;
+        }
+    }
+}
+
+///A Bitboard with whichever storage fits the board. The solver matches on it once per check and
+///runs the check on the inner board, so that the set operations in between don't dispatch.
+#[derive(Clone, Debug)]
+pub enum AnyBitboard {
+    Inline(Bitboard<[u64; INLINE]>),
+    Heap(Bitboard<Vec<u64>>),
+}
+
+impl AnyBitboard {
+    pub fn from_board(board: &Board) -> AnyBitboard {
+        if (board.rows * (board.cols + 1)).div_ceil(64) <= INLINE {
+            AnyBitboard::Inline(Bitboard::from_board(board))
+        } else {
+            AnyBitboard::Heap(Bitboard::from_board(board))
+        }
+    }
+
+    pub fn to_board(&self) -> Board {
+        match self {
+            AnyBitboard::Inline(bits) => bits.to_board(),
+            AnyBitboard::Heap(bits) => bits.to_board(),
+        }
+    }
+
+    pub fn set(&mut self, c: Coord, color: u8) {
+        match self {
+            AnyBitboard::Inline(bits) => bits.set(c, color),
+            AnyBitboard::Heap(bits) => bits.set(c, color),
+        }
+    }
+
+    pub fn clear(&mut self, c: Coord) {
+        match self {
+            AnyBitboard::Inline(bits) => bits.clear(c),
+            AnyBitboard::Heap(bits) => bits.clear(c),
+        }
+    }
+
+    ///Number of empty cells
+    pub fn empty_count(&self) -> usize {
+        match self {
+            AnyBitboard::Inline(bits) => bits.empty().count(),
+            AnyBitboard::Heap(bits) => bits.empty().count(),
+        }
+    }
+
+    ///Number of connected regions of empty cells
+    pub fn region_count(&self) -> usize {
+        match self {
+            AnyBitboard::Inline(bits) => bits.regions(&bits.empty()).len(),
+            AnyBitboard::Heap(bits) => bits.regions(&bits.empty()).len(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Bitboard<W> {
+    pub rows: usize,
+    pub cols: usize,
+    stride: usize,       // cols plus the spare column
+    on_board: Bits<W>,   // Every real cell
+    pub filled: Bits<W>, // Paths and heads of any colour
+    heads: Bits<W>,
+    colors: Vec<Bits<W>>, // Indexed by colour
+    none: Bits<W>,        // Sized for the board, for starting new sets from
+}
+
+impl<W: Words> Bitboard<W> {
+    pub fn from_board(board: &Board) -> Bitboard<W> {
+        let stride = board.cols + 1;
+        let none = Bits::new(board.rows * stride);
+        let mut out = Bitboard {
+            rows: board.rows,
+            cols: board.cols,
+            stride,
+            on_board: none.clone(),
+            filled: none.clone(),
+            heads: none.clone(),
+            colors: Vec::new(),
+            none,
+        };
+        for i in 0..board.len() {
+            let c = board.inverse_ind(i);
+            out.on_board.set(out.bit(c));
+            match board[i] {
+                Cell::Empty => {}
+                Cell::Path { color } => out.set(c, color),
+                Cell::Head { color } => {
+                    out.set(c, color);
+                    out.heads.set(out.bit(c));
+                }
+            }
+        }
+        out
+    }
+
+    pub fn to_board(&self) -> Board {
+        let mut board = Board::new(self.rows, self.cols);
+        for (color, cells) in self.colors.iter().enumerate() {
+            for i in cells.iter() {
+                board[self.coord(i)] = if self.heads.get(i) {
+                    Cell::Head { color: color as u8 }
+                } else {
+                    Cell::Path { color: color as u8 }
+                };
+            }
+        }
+        board
+    }
+
+    pub fn bit(&self, c: Coord) -> usize {
+        c.0 * self.stride + c.1
+    }
+
+    pub fn coord(&self, i: usize) -> Coord {
+        (i / self.stride, i % self.stride)
+    }
+
+    ///Fills a cell with a path of the given colour
+    pub fn set(&mut self, c: Coord, color: u8) {
+        let i = self.bit(c);
+        if self.colors.len() <= color as usize {
+            self.colors.resize(color as usize + 1, self.none.clone());
+        }
+        self.colors[color as usize].set(i);
+        self.filled.set(i);
+    }
+
+    pub fn clear(&mut self, c: Coord) {
+        let i = self.bit(c);
+        self.filled.clear(i);
+        self.heads.clear(i);
+        for cells in &mut self.colors {
+            cells.clear(i);
+        }
+    }
+
+    pub fn cells(&self, cs: &[Coord]) -> Bits<W> {
+        let mut out = self.none.clone();
+        for c in cs {
+            out.set(self.bit(*c));
+        }
+        out
+    }
+
+    pub fn empty(&self) -> Bits<W> {
+        let mut out = self.on_board.clone();
+        out.remove(&self.filled);
+        out
+    }
+
+    pub fn color(&self, color: u8) -> Bits<W> {
+        self.colors
+            .get(color as usize)
+            .unwrap_or(&self.none)
+            .clone()
+    }
+
+    // The set moved one step in each of the four directions, clipped to the board
+    fn shifted(&self, cells: &Bits<W>) -> [Bits<W>; 4] {
+        let mut out: [Bits<W>; 4] = std::array::from_fn(|_| self.none.clone());
+        out[0].or_shr(cells, self.stride);
+        out[1].or_shr(cells, 1);
+        out[2].or_shl(cells, self.stride);
+        out[3].or_shl(cells, 1);
+        for s in &mut out {
+            *s &= &self.on_board;
+        }
+        out
+    }
+
+    // Adds every cell next to a cell of the set to `out`, on the board or not
+    fn spread(&self, cells: &Bits<W>, out: &mut Bits<W>) {
+        out.or_shr(cells, self.stride);
+        out.or_shr(cells, 1);
+        out.or_shl(cells, self.stride);
+        out.or_shl(cells, 1);
+    }
+
+    ///Every cell next to at least one cell of the set
+    pub fn neighbors(&self, cells: &Bits<W>) -> Bits<W> {
+        let mut out = self.none.clone();
+        self.spread(cells, &mut out);
+        out &= &self.on_board;
+        out
+    }
+
+    ///Cells of `within` next to fewer than two cells of `open`
+    pub fn lonely(&self, within: &Bits<W>, open: &Bits<W>) -> Bits<W> {
+        // Next to two or more: both of a pair of directions, or one of each pair
+        let [mut a, b, mut c, d] = self.shifted(open);
+        let mut both = a.clone();
+        both &= &b;
+        a |= &b;
+        let mut other = c.clone();
+        other &= &d;
+        both |= &other;
+        c |= &d;
+        a &= &c;
+        a |= &both;
+        let mut out = within.clone();
+        out.remove(&a);
+        out
+    }
+
+    ///Every cell of `within` that can be reached from `seed` without leaving `within`
+    pub fn flood(&self, seed: &Bits<W>, within: &Bits<W>) -> Bits<W> {
+        let mut reached = seed.clone();
+        reached &= within;
+        let mut next = reached.clone();
+        loop {
+            self.spread(&reached, &mut next);
+            next &= within;
+            if next == reached {
+                return reached;
+            }
+            reached.clone_from(&next);
+        }
+    }
+
+    ///Splits a set of cells into its connected regions, in order of their lowest cell
+    pub fn regions(&self, within: &Bits<W>) -> Vec<Bits<W>> {
+        let mut left = within.clone();
+        let mut out = Vec::new();
+        while let Some(i) = left.first() {
+            let mut seed = self.none.clone();
+            seed.set(i);
+            let region = self.flood(&seed, &left);
+            left.remove(&region);
+            out.push(region);
+        }
+        out
+    }
+}
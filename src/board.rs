@@ -1,8 +1,8 @@
+use std::fmt;
 use std::io;
 use std::ops::{Index, IndexMut};
 
-use crate::COLS;
-use crate::ROWS;
+use crate::colors::Scheme;
 use colored::ColoredString;
 use colored::Colorize;
 
@@ -23,16 +23,98 @@ impl Cell {
     }
 
     pub fn is_head(&self) -> bool {
+        matches!(self, Cell::Head { .. })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Cell::Empty)
+    }
+
+    ///Single character for the cell: its letter, capital for heads, or for colours past Z an @
+    ///for heads and a + for paths
+    pub fn letter(&self) -> char {
+        match *self {
+            Cell::Empty => '.',
+            Cell::Path { color } if color < LETTERS => (color + b'a') as char,
+            Cell::Head { color } if color < LETTERS => (color + b'A') as char,
+            Cell::Path { .. } => '+',
+            Cell::Head { .. } => '@',
+        }
+    }
+
+    // Reads a cell of the numeric format: "." for empty, the colour's number for a path, and the
+    // number followed by "*" for a head
+    fn from_number(token: &str) -> Cell {
+        if token == "." {
+            return Cell::Empty;
+        }
+        let color = |n: &str| n.parse().expect("Bad cell in board");
+        match token.strip_suffix('*') {
+            Some(n) => Cell::Head { color: color(n) },
+            None => Cell::Path {
+                color: color(token),
+            },
+        }
+    }
+
+    fn to_number(self) -> String {
         match self {
-            Cell::Head { color } => true,
-            _ => false,
+            Cell::Empty => ".".to_string(),
+            Cell::Path { color } => color.to_string(),
+            Cell::Head { color } => format!("{}*", color),
         }
     }
+}
 
-    pub fn is_empty(&self) -> bool {
+///Colours that have a letter; boards with more are written in the numeric format
+pub const LETTERS: u8 = 26;
+
+///A colour's letter, or its number past Z
+pub fn color_name(color: u8) -> String {
+    if color < LETTERS {
+        ((color + b'A') as char).to_string()
+    } else {
+        color.to_string()
+    }
+}
+
+///One colour's flow, as the cells from one of its heads to the other
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorPath {
+    pub color: u8,
+    pub cells: Vec<(usize, usize)>,
+}
+
+///Why Board::paths couldn't trace a colour from head to head
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathError {
+    Heads { color: u8, count: usize },        // Not exactly two heads
+    Branch { color: u8, at: (usize, usize) }, // More than one way on from this cell
+    Broken { color: u8, at: (usize, usize) }, // The path stops here short of the other head
+    Loop { color: u8, at: (usize, usize) },   // Cells of the colour off its path, e.g. a loop
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let letter = |color: &u8| color_name(*color);
         match self {
-            Cell::Empty => true,
-            _ => false,
+            PathError::Heads { color, count } => {
+                write!(f, "colour {} has {} heads", letter(color), count)
+            }
+            PathError::Branch { color, at } => {
+                write!(f, "colour {} branches at {:?}", letter(color), at)
+            }
+            PathError::Broken { color, at } => {
+                write!(f, "colour {} stops short at {:?}", letter(color), at)
+            }
+            PathError::Loop { color, at } => {
+                write!(
+                    f,
+                    "colour {} has cells off its path at {:?}",
+                    letter(color),
+                    at
+                )
+            }
         }
     }
 }
@@ -81,90 +163,87 @@ impl Board {
             out[1] = self[(row, col - 1)];
         }
 
-        if row < ROWS - 1 {
+        if row < self.rows - 1 {
             out[2] = self[(row + 1, col)];
         }
 
-        if col < COLS - 1 {
+        if col < self.cols - 1 {
             out[3] = self[(row, col + 1)];
         }
         out
     }
 
+    ///Coordinates of a cell's neighbors that are on the board
+    pub fn neighbors(&self, row: usize, col: usize) -> impl Iterator<Item = (usize, usize)> {
+        let (rows, cols) = (self.rows, self.cols);
+        [
+            (row.wrapping_sub(1), col),
+            (row, col.wrapping_sub(1)),
+            (row + 1, col),
+            (row, col + 1),
+        ]
+        .into_iter()
+        .filter(move |(r, c)| *r < rows && *c < cols)
+    }
+
     ///Returns a cell's 4 empty neighbors. Edges or nonempty are represented as None.
     pub fn empty_neighbors(&self, row: usize, col: usize) -> [Option<(usize, usize)>; 4] {
         let mut out = [None; 4];
-        if row > 0 {
-            if self[(row - 1, col)] == Cell::Empty {
-                out[0] = Some((row - 1, col));
-            }
+        if row > 0 && self[(row - 1, col)] == Cell::Empty {
+            out[0] = Some((row - 1, col));
         }
 
-        if col > 0 {
-            if self[(row, col - 1)] == Cell::Empty {
-                out[1] = Some((row, col - 1));
-            }
+        if col > 0 && self[(row, col - 1)] == Cell::Empty {
+            out[1] = Some((row, col - 1));
         }
 
-        if row < ROWS - 1 {
-            if self[(row + 1, col)] == Cell::Empty {
-                out[2] = Some((row + 1, col));
-            }
+        if row < self.rows - 1 && self[(row + 1, col)] == Cell::Empty {
+            out[2] = Some((row + 1, col));
         }
 
-        if col < COLS - 1 {
-            if self[(row, col + 1)] == Cell::Empty {
-                out[3] = Some((row, col + 1));
-            }
+        if col < self.cols - 1 && self[(row, col + 1)] == Cell::Empty {
+            out[3] = Some((row, col + 1));
         }
         out
     }
 
     pub fn neighbor_head(&self, row: usize, col: usize, color: u8) -> Option<(usize, usize)> {
         let target = Cell::Head { color };
-        if row > 0 {
-            if self[(row - 1, col)] == target {
-                return Some((row - 1, col));
-            }
+        if row > 0 && self[(row - 1, col)] == target {
+            return Some((row - 1, col));
         }
-        if row < ROWS - 1 {
-            if self[(row + 1, col)] == target {
-                return Some((row + 1, col));
-            }
+        if row < self.rows - 1 && self[(row + 1, col)] == target {
+            return Some((row + 1, col));
         }
-        if col > 0 {
-            if self[(row, col - 1)] == target {
-                return Some((row, col - 1));
-            }
+        if col > 0 && self[(row, col - 1)] == target {
+            return Some((row, col - 1));
         }
-        if col < COLS - 1 {
-            if self[(row, col + 1)] == target {
-                return Some((row, col + 1));
-            }
+        if col < self.cols - 1 && self[(row, col + 1)] == target {
+            return Some((row, col + 1));
         }
-        return None;
+        None
     }
 
     ///For graphics: Every single path cell can be drawn by describing the neighbors of the same color it has in the following way:
     pub fn orientation(&self, index: usize) -> u8 {
         let mut out = 0;
-        let neighbors = self.neighbors_or_empty(Self::inverse_ind(index));
+        let neighbors = self.neighbors_or_empty(self.inverse_ind(index));
         let own_color = self[index].color();
-        for i in 0..4 {
-            if !neighbors[i].is_empty() && neighbors[i].color() == own_color {
+        for (i, neighbor) in neighbors.iter().enumerate() {
+            if !neighbor.is_empty() && neighbor.color() == own_color {
                 out |= 1 << i;
             }
         }
         out
     }
 
-    pub fn inverse_ind(index: usize) -> (usize, usize) {
-        (index / COLS, index % COLS)
+    pub fn inverse_ind(&self, index: usize) -> (usize, usize) {
+        (index / self.cols, index % self.cols)
     }
 
     pub fn is_solved(&self) -> bool {
-        for i in 0..ROWS {
-            for j in 0..COLS {
+        for i in 0..self.rows {
+            for j in 0..self.cols {
                 match self[(i, j)] {
                     Cell::Empty => return false,
                     Cell::Path { color } => {
@@ -180,13 +259,13 @@ impl Board {
                 };
             }
         }
-        return true;
+        true
     }
 
     ///Checks whether paths are laid out legally
     pub fn is_legal(&self) -> bool {
-        for i in 0..ROWS {
-            for j in 0..COLS {
+        for i in 0..self.rows {
+            for j in 0..self.cols {
                 match self[(i, j)] {
                     Cell::Empty => {}
                     Cell::Path { color } => {
@@ -203,24 +282,18 @@ impl Board {
                 };
             }
         }
-        return true;
+        true
     }
 
     ///Checks whether all colors appear only twice
     pub fn is_valid(&self) -> bool {
-        let mut seen_colors: u64 = 0; //Double the number of possible colors
+        let mut heads = [0usize; 256];
         for cell in &self.cells {
-            match cell {
-                Cell::Head { color } => {
-                    seen_colors += 1 << (color * 2);
-                    if (seen_colors & 3 << (color * 2)) == 3 << color {
-                        return false;
-                    }
-                }
-                _ => {}
+            if let Cell::Head { color } = cell {
+                heads[*color as usize] += 1;
             }
         }
-        seen_colors & 0x5555_5555_5555_5555 == 0
+        heads.iter().all(|n| *n == 0 || *n == 2)
     }
 
     pub fn is_full(&self) -> bool {
@@ -239,17 +312,26 @@ impl Board {
             })
     }
 
+    ///Reads a board in either text format: letters (see to_letters) or numbers (see to_numbers)
     pub fn load_board(board_str: &str, rows: usize, cols: usize) -> Self {
+        if board_str.bytes().any(|byte| byte.is_ascii_digit()) {
+            let cells: Vec<Cell> = board_str
+                .split_whitespace()
+                .map(Cell::from_number)
+                .collect();
+            assert_eq!(cells.len(), rows * cols, "Board doesn't match its size");
+            return Self {
+                rows,
+                cols,
+                cells: cells.into_boxed_slice(),
+            };
+        }
         let board_vec = board_str
             .bytes()
             .filter(|byte| byte.is_ascii_alphanumeric() || byte.is_ascii_punctuation())
             .map(|byte| match byte {
-                b'a'..=b'z' => Cell::Path {
-                    color: (byte - b'a') as u8,
-                },
-                b'A'..=b'Z' => Cell::Head {
-                    color: (byte - b'A') as u8,
-                },
+                b'a'..=b'z' => Cell::Path { color: byte - b'a' },
+                b'A'..=b'Z' => Cell::Head { color: byte - b'A' },
                 _ => Cell::Empty,
             })
             .collect::<Vec<Cell>>();
@@ -261,6 +343,117 @@ impl Board {
         }
     }
 
+    ///Loads a board written one row per line, taking its size from the text
+    pub fn parse(text: &str) -> Self {
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        let cols = match lines.first() {
+            Some(l) if l.bytes().any(|byte| byte.is_ascii_digit()) => l.split_whitespace().count(),
+            Some(l) => l.len(),
+            None => 0,
+        };
+        Self::load_board(text, lines.len(), cols)
+    }
+
+    ///The board in letters if every colour has one, otherwise in numbers
+    pub fn to_text(&self) -> String {
+        if self.iter().all(|c| c.is_empty() || c.color() < LETTERS) {
+            self.to_letters()
+        } else {
+            self.to_numbers()
+        }
+    }
+
+    ///The board in the numeric format load_board reads, for boards with more colours than
+    ///letters: one row per line, cells separated by spaces, each "." if empty, else its colour's
+    ///number from 0, followed by "*" for a head
+    pub fn to_numbers(&self) -> String {
+        let mut out = String::new();
+        for i in 0..self.rows {
+            let row: Vec<String> = (0..self.cols).map(|j| self[(i, j)].to_number()).collect();
+            out.push_str(&row.join(" "));
+            out.push('\n');
+        }
+        out
+    }
+
+    ///The board in the same letter format load_board reads: heads in capitals, paths in lower
+    ///case, empty cells as dots
+    pub fn to_letters(&self) -> String {
+        let mut out = String::new();
+        for i in 0..self.rows {
+            for j in 0..self.cols {
+                out.push(self[(i, j)].letter());
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    ///Each colour's path from one head to the other, in order of colour. Fails unless every colour
+    ///has two heads joined by a single unbranching path and every filled cell is on one.
+    pub fn paths(&self) -> Result<Vec<ColorPath>, PathError> {
+        let mut colors: Vec<u8> = self
+            .iter()
+            .filter(|c| c.is_head())
+            .map(Cell::color)
+            .collect();
+        colors.sort();
+        colors.dedup();
+        let mut visited = vec![false; self.len()];
+        let mut paths = Vec::new();
+        for color in colors {
+            let heads: Vec<(usize, usize)> = (0..self.len())
+                .filter(|i| self[*i] == Cell::Head { color })
+                .map(|i| self.inverse_ind(i))
+                .collect();
+            if heads.len() != 2 {
+                return Err(PathError::Heads {
+                    color,
+                    count: heads.len(),
+                });
+            }
+
+            let mut cells = vec![heads[0]];
+            visited[heads[0].0 * self.cols + heads[0].1] = true;
+            loop {
+                let at = *cells.last().unwrap();
+                let back = cells.len().checked_sub(2).map(|i| cells[i]);
+                let mut next = self
+                    .neighbors(at.0, at.1)
+                    .filter(|n| Some(*n) != back)
+                    .filter(|n| !self[*n].is_empty() && self[*n].color() == color);
+                let ahead = next.next();
+                if at == heads[1] && ahead.is_none() {
+                    break;
+                }
+                if at == heads[1] || next.next().is_some() {
+                    return Err(PathError::Branch { color, at });
+                }
+                let Some(n) = ahead else {
+                    return Err(PathError::Broken { color, at });
+                };
+                if visited[n.0 * self.cols + n.1] {
+                    return Err(PathError::Branch { color, at: n });
+                }
+                visited[n.0 * self.cols + n.1] = true;
+                cells.push(n);
+            }
+            paths.push(ColorPath { color, cells });
+        }
+
+        match (0..self.len()).find(|i| !self[*i].is_empty() && !visited[*i]) {
+            Some(i) => Err(PathError::Loop {
+                color: self[i].color(),
+                at: self.inverse_ind(i),
+            }),
+            None => Ok(paths),
+        }
+    }
+
     pub fn set_cell(board: &mut Board, row: usize, col: usize, cell: Cell) {
         board[(row, col)] = cell;
     }
@@ -280,60 +473,35 @@ impl Board {
         self.rows * self.cols
     }
 
-    const COLORS: &[(u8, u8, u8)] = &[
-        (255, 0, 0),     // Red
-        (0, 255, 0),     // Lime
-        (0, 0, 255),     // Blue
-        (255, 255, 0),   // Yellow
-        (0, 255, 255),   // Cyan
-        (255, 0, 255),   // Magenta
-        (255, 165, 0),   // Orange
-        (128, 0, 128),   // Purple
-        (0, 128, 128),   // Teal
-        (128, 128, 0),   // Olive
-        (0, 0, 128),     // Navy
-        (139, 69, 19),   // SaddleBrown
-        (255, 192, 203), // Pink
-        (173, 255, 47),  // GreenYellow
-        (70, 130, 180),  // SteelBlue
-        (210, 105, 30),  // Chocolate
-        (154, 205, 50),  // YellowGreen
-        (255, 20, 147),  // DeepPink
-        (186, 85, 211),  // MediumOrchid
-        (60, 179, 113),  // MediumSeaGreen
-        (72, 61, 139),   // DarkSlateBlue
-        (0, 206, 209),   // DarkTurquoise
-        (244, 164, 96),  // SandyBrown
-        (0, 100, 0),     // DarkGreen
-        (199, 21, 133),  // MediumVioletRed
-        (112, 128, 144), // SlateGray
-    ];
-
     pub fn print(&self) {
         print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
+        print!("{}", self.render(None, Scheme::default()));
+    }
+
+    ///Renders the board as truecolor terminal text, one line per row, with the cursor cell reversed
+    pub fn render(&self, cursor: Option<(usize, usize)>, scheme: Scheme) -> String {
+        let n_colors = self.num_colors();
         let mut out = String::new();
         for i in 0..self.rows {
             for j in 0..self.cols {
-                let c = match self[(i, j)] {
-                    Cell::Empty => ColoredString::from("."),
-                    Cell::Path { color } => {
-                        let rgb = Self::COLORS[color as usize];
-                        ((color + b'a') as char)
-                            .encode_utf8(&mut [0; 4])
-                            .on_truecolor(rgb.0, rgb.1, rgb.2)
-                    }
-                    Cell::Head { color } => {
-                        let rgb = Self::COLORS[color as usize];
-                        ((color + b'A') as char)
-                            .encode_utf8(&mut [0; 4])
-                            .on_truecolor(rgb.0, rgb.1, rgb.2)
-                    }
+                let cell = self[(i, j)];
+                let c = if cell.is_empty() {
+                    ColoredString::from(".")
+                } else {
+                    let rgb = scheme.rgb(cell.color(), n_colors);
+                    cell.letter()
+                        .encode_utf8(&mut [0; 4])
+                        .on_truecolor(rgb.0, rgb.1, rgb.2)
                 };
-                out.push_str(&c.to_string());
+                if cursor == Some((i, j)) {
+                    out.push_str(&c.reversed().to_string());
+                } else {
+                    out.push_str(&c.to_string());
+                }
             }
             out.push('\n');
         }
-        print!("{}", out);
+        out
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &Cell> {
@@ -358,7 +526,7 @@ impl Index<(usize, usize)> for Board {
             panic!();
         }
 
-        &self.cells[row * COLS + col]
+        &self.cells[row * self.cols + col]
     }
 }
 
@@ -381,7 +549,7 @@ impl IndexMut<(usize, usize)> for Board {
             panic!();
         }
 
-        &mut self.cells[row * COLS + col]
+        &mut self.cells[row * self.cols + col]
     }
 }
 
@@ -396,3 +564,67 @@ impl IndexMut<usize> for Board {
 }
 
 // fn is_impossible(b: Board) -> bool {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_of_a_solved_board() {
+        let b = Board::parse("AaaA\nBbbB\n");
+        let row = |r| (0..4).map(|c| (r, c)).collect::<Vec<_>>();
+        assert_eq!(
+            b.paths(),
+            Ok(vec![
+                ColorPath {
+                    color: 0,
+                    cells: row(0)
+                },
+                ColorPath {
+                    color: 1,
+                    cells: row(1)
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn paths_need_two_heads() {
+        let b = Board::parse("A...\n....\n");
+        assert_eq!(b.paths(), Err(PathError::Heads { color: 0, count: 1 }));
+    }
+
+    #[test]
+    fn paths_must_not_branch() {
+        let b = Board::parse("AaA\n.a.\n");
+        let at = (0, 1);
+        assert_eq!(b.paths(), Err(PathError::Branch { color: 0, at }));
+    }
+
+    #[test]
+    fn paths_must_reach_the_other_head() {
+        let b = Board::parse("A.A.\n....\n");
+        let at = (0, 0);
+        assert_eq!(b.paths(), Err(PathError::Broken { color: 0, at }));
+    }
+
+    #[test]
+    fn paths_cover_every_filled_cell() {
+        let b = Board::parse("AA..\n..aa\n..aa\n....\n");
+        let at = (1, 2);
+        assert_eq!(b.paths(), Err(PathError::Loop { color: 0, at }));
+    }
+
+    #[test]
+    fn text_round_trip_past_the_letters() {
+        let mut b = Board::new(3, 30);
+        for col in 0..30 {
+            let color = col as u8;
+            b[(0, col)] = Cell::Head { color };
+            b[(1, col)] = Cell::Path { color };
+            b[(2, col)] = Cell::Head { color };
+        }
+        assert!(b.num_colors() > LETTERS as usize);
+        assert_eq!(Board::parse(&b.to_text()), b);
+    }
+}
@@ -0,0 +1,351 @@
+// CNF encoding of a puzzle, after https://mzucker.github.io/2016/08/28/flow-solver.html
+//
+// Every cell gets one variable per colour, and every cell that isn't a head one variable per shape
+// a path can take through it (the six ways to pick two of its four sides). The clauses say:
+// - every cell has exactly one colour, and heads and any path cells already drawn keep theirs
+// - a head has exactly one neighbour of its colour
+// - every other cell has exactly one shape, and no shape leaves the board
+// - a shape's two sides lead to cells of the same colour, and the other sides to different ones
+//
+// That leaves one gap: a loop of path cells that touches no head. Loops are found after solving,
+// forbidden with a clause each, and the solver runs again.
+//
+// The CNF can also be written out in DIMACS format for an outside SAT solver (`flow4 cnf`), and a
+// model from one read back into a board (`flow4 decode`).
+
+use std::fs;
+
+use crate::backend::Control;
+use crate::board::{Board, Cell, LETTERS, color_name};
+use crate::sat::{Sat, SatResult};
+use crate::solver::Solver;
+
+// Pairs of sides (up, left, down, right, as in Board::neighbors_or_empty) for each shape
+const SHAPES: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+
+pub struct Cnf {
+    pub vars: usize,
+    pub clauses: Vec<Vec<i32>>, // DIMACS literals: variables from 1, negative when negated
+}
+
+impl Cnf {
+    pub fn dimacs(&self) -> String {
+        let mut out = format!("p cnf {} {}\n", self.vars, self.clauses.len());
+        for clause in &self.clauses {
+            for l in clause {
+                out.push_str(&l.to_string());
+                out.push(' ');
+            }
+            out.push_str("0\n");
+        }
+        out
+    }
+}
+
+pub struct Encoding {
+    rows: usize,
+    cols: usize,
+    colors: Vec<u8>,  // Colours on the board; variable k of a cell is colors[k]
+    heads: Vec<bool>, // By cell index
+    pub cnf: Cnf,
+}
+
+///Counts from one SAT run, plus how many loops had to be ruled out
+#[derive(Default)]
+pub struct SatStats {
+    pub vars: usize,
+    pub clauses: usize,
+    pub conflicts: u64,
+    pub decisions: u64,
+    pub loops: usize,
+    pub gave_up: bool, // Hit the conflict limit
+}
+
+impl Encoding {
+    pub fn new(board: &Board) -> Encoding {
+        let mut colors: Vec<u8> = board
+            .iter()
+            .filter(|c| c.is_head())
+            .map(Cell::color)
+            .collect();
+        colors.sort();
+        colors.dedup();
+        let len = board.len();
+        // Paths the search wouldn't carry on from, such as one not joined to a head or one that
+        // forks, can't be part of a solution even where every cell has the right colour
+        let consistent = Solver::consistent(board);
+        let mut e = Encoding {
+            rows: board.rows,
+            cols: board.cols,
+            heads: board.iter().map(Cell::is_head).collect(),
+            cnf: Cnf {
+                vars: len * colors.len() + len * SHAPES.len(),
+                clauses: Vec::new(),
+            },
+            colors,
+        };
+
+        for i in 0..len {
+            let neighbors = e.sides(i);
+            let colors: Vec<i32> = (0..e.colors.len()).map(|k| e.color_var(i, k)).collect();
+            if let Cell::Head { color } = board[i] {
+                let k = e.colors.iter().position(|c| *c == color).unwrap();
+                e.cnf.clauses.push(vec![colors[k]]);
+                e.exactly_one(&colors);
+                let same: Vec<i32> = neighbors
+                    .iter()
+                    .flatten()
+                    .map(|n| e.color_var(*n, k))
+                    .collect();
+                e.exactly_one(&same);
+                for t in 0..SHAPES.len() {
+                    e.cnf.clauses.push(vec![-e.shape_var(i, t)]);
+                }
+                continue;
+            }
+
+            if let Cell::Path { color } = board[i] {
+                // Nor can a colour without heads: the empty clause makes that plain
+                match e.colors.iter().position(|c| *c == color) {
+                    Some(k) if consistent => e.cnf.clauses.push(vec![colors[k]]),
+                    _ => e.cnf.clauses.push(Vec::new()),
+                }
+            }
+            e.exactly_one(&colors);
+            let mut shapes = Vec::new();
+            for (t, (a, b)) in SHAPES.into_iter().enumerate() {
+                let shape = e.shape_var(i, t);
+                if neighbors[a].is_none() || neighbors[b].is_none() {
+                    e.cnf.clauses.push(vec![-shape]);
+                    continue;
+                }
+                shapes.push(shape);
+                for (side, n) in neighbors.iter().enumerate() {
+                    let Some(n) = n else { continue };
+                    for k in 0..e.colors.len() {
+                        let (here, there) = (e.color_var(i, k), e.color_var(*n, k));
+                        if side == a || side == b {
+                            e.cnf.clauses.push(vec![-shape, -here, there]);
+                            e.cnf.clauses.push(vec![-shape, here, -there]);
+                        } else {
+                            e.cnf.clauses.push(vec![-shape, -here, -there]);
+                        }
+                    }
+                }
+            }
+            e.exactly_one(&shapes);
+        }
+        e
+    }
+
+    // Variable for cell i having colour colors[k]
+    fn color_var(&self, i: usize, k: usize) -> i32 {
+        (i * self.colors.len() + k + 1) as i32
+    }
+
+    // Variable for the path through cell i having shape t
+    fn shape_var(&self, i: usize, t: usize) -> i32 {
+        (self.rows * self.cols * self.colors.len() + i * SHAPES.len() + t + 1) as i32
+    }
+
+    // Neighbouring cell indices by side, None off the board
+    fn sides(&self, i: usize) -> [Option<usize>; 4] {
+        let (row, col) = (i / self.cols, i % self.cols);
+        [
+            (row > 0).then(|| i - self.cols),
+            (col > 0).then(|| i - 1),
+            (row + 1 < self.rows).then(|| i + self.cols),
+            (col + 1 < self.cols).then(|| i + 1),
+        ]
+    }
+
+    fn exactly_one(&mut self, vars: &[i32]) {
+        self.cnf.clauses.push(vars.to_vec());
+        for (j, a) in vars.iter().enumerate() {
+            for b in &vars[j + 1..] {
+                self.cnf.clauses.push(vec![-a, -b]);
+            }
+        }
+    }
+
+    ///Reads the board back out of an assignment (indexed from variable 1). Cells the assignment
+    ///gives no colour are left empty.
+    pub fn decode(&self, model: &[bool]) -> Board {
+        let mut board = Board::new(self.rows, self.cols);
+        for i in 0..board.len() {
+            let Some(k) =
+                (0..self.colors.len()).find(|k| model[self.color_var(i, *k) as usize - 1])
+            else {
+                continue;
+            };
+            let color = self.colors[k];
+            board[i] = if self.heads[i] {
+                Cell::Head { color }
+            } else {
+                Cell::Path { color }
+            };
+        }
+        board
+    }
+
+    // DIMACS comments describing the variables, for whoever reads the file
+    fn legend(&self) -> String {
+        let names = if self.colors.iter().all(|c| *c < LETTERS) {
+            self.colors.iter().map(|c| color_name(*c)).collect()
+        } else {
+            let numbers: Vec<String> = self.colors.iter().map(u8::to_string).collect();
+            numbers.join(" ")
+        };
+        let cells = self.rows * self.cols;
+        format!(
+            "c flow4 puzzle, {} rows x {} cols, colours {}\n\
+             c var cell * {} + colour + 1: cell (row * {} + col) has that colour, colours in the order above\n\
+             c var {} + cell * 6 + shape + 1: the path through the cell joins sides \
+             up-left, up-down, up-right, left-down, left-right or down-right\n",
+            self.rows,
+            self.cols,
+            names,
+            self.colors.len(),
+            self.cols,
+            cells * self.colors.len(),
+        )
+    }
+
+    // Cells on paths that never reach a head, grouped into loops
+    pub fn loops(&self, board: &Board) -> Vec<Vec<usize>> {
+        let mut reached = vec![false; board.len()];
+        for start in (0..board.len()).filter(|i| self.heads[*i]) {
+            let mut stack = vec![start];
+            reached[start] = true;
+            while let Some(i) = stack.pop() {
+                for n in self.sides(i).into_iter().flatten() {
+                    if !reached[n] && board[n].color() == board[i].color() {
+                        reached[n] = true;
+                        stack.push(n);
+                    }
+                }
+            }
+        }
+        let mut loops = Vec::new();
+        for start in 0..board.len() {
+            if reached[start] {
+                continue;
+            }
+            let mut cells = vec![start];
+            reached[start] = true;
+            let mut k = 0;
+            while k < cells.len() {
+                let i = cells[k];
+                k += 1;
+                for n in self.sides(i).into_iter().flatten() {
+                    if !reached[n] && board[n].color() == board[i].color() {
+                        reached[n] = true;
+                        cells.push(n);
+                    }
+                }
+            }
+            loops.push(cells);
+        }
+        loops
+    }
+
+    ///Solves the encoding, ruling out loops until none are left. None if the puzzle has no
+    ///solution or the SAT solver gave up or was cancelled.
+    pub fn solve(mut self, conflict_limit: u64, control: &Control) -> (Option<Board>, SatStats) {
+        let mut stats = SatStats::default();
+        loop {
+            let mut sat = Sat::new(self.cnf.vars, &self.cnf.clauses);
+            let result = sat.solve(conflict_limit.saturating_sub(stats.conflicts), control);
+            stats.vars = self.cnf.vars;
+            stats.clauses = self.cnf.clauses.len();
+            stats.conflicts += sat.conflicts;
+            stats.decisions += sat.decisions;
+            let model = match result {
+                SatResult::Sat(model) => model,
+                SatResult::Unsat => return (None, stats),
+                SatResult::GaveUp => {
+                    stats.gave_up = true;
+                    return (None, stats);
+                }
+            };
+
+            let board = self.decode(&model);
+            let loops = self.loops(&board);
+            if loops.is_empty() {
+                return (Some(board), stats);
+            }
+            // The same cells could still be coloured this way, just not with these exact shapes
+            for cells in loops {
+                let clause = cells
+                    .iter()
+                    .map(|i| {
+                        let t = (0..SHAPES.len())
+                            .find(|t| model[self.shape_var(*i, *t) as usize - 1])
+                            .unwrap();
+                        -self.shape_var(*i, t)
+                    })
+                    .collect();
+                self.cnf.clauses.push(clause);
+                stats.loops += 1;
+            }
+        }
+    }
+}
+
+///Solves a board by SAT; see Encoding
+pub fn solve(board: &Board, conflict_limit: u64, control: &Control) -> (Option<Board>, SatStats) {
+    Encoding::new(board).solve(conflict_limit, control)
+}
+
+///Writes the CNF for a puzzle file in DIMACS format, to `out` or else standard output
+pub fn export(puzzle: &str, out: Option<&str>) {
+    let encoding = Encoding::new(&load(puzzle));
+    let text = encoding.legend() + &encoding.cnf.dimacs();
+    match out {
+        Some(path) => fs::write(path, text).expect("Couldn't write the CNF file"),
+        None => print!("{}", text),
+    }
+}
+
+///Prints the board a SAT solver's model describes for a puzzle file, in its text format
+pub fn import(puzzle: &str, model: &str) {
+    let encoding = Encoding::new(&load(puzzle));
+    let text = fs::read_to_string(model).expect("Couldn't read the model file");
+    let board = encoding.decode(&parse_model(&text, encoding.cnf.vars));
+    print!("{}", board.to_text());
+    if board.iter().any(Cell::is_empty) {
+        eprintln!("The model leaves some cells without a colour");
+    } else if let Err(e) = board.paths() {
+        eprintln!("The model is not a solution: {}", e);
+    } else if !board.is_solved() {
+        eprintln!("The model is not a solution");
+    }
+}
+
+// A puzzle file in either text format, with any path cells in it cleared
+fn load(path: &str) -> Board {
+    let mut board = Board::parse(&fs::read_to_string(path).expect("Couldn't read the puzzle file"));
+    board.strip();
+    board
+}
+
+// Reads a model as SAT solvers print it: the true and false literals as signed numbers, optionally
+// on "v" lines among "s" and "c" lines. Variables it doesn't mention are false.
+fn parse_model(text: &str, vars: usize) -> Vec<bool> {
+    let mut model = vec![false; vars];
+    for line in text.lines() {
+        let line = line.trim_start();
+        if line.starts_with('c') || line.starts_with('s') {
+            continue;
+        }
+        for l in line
+            .split_whitespace()
+            .filter_map(|w| w.parse::<i64>().ok())
+        {
+            if l > 0 && (l as usize) <= vars {
+                model[l as usize - 1] = true;
+            }
+        }
+    }
+    model
+}
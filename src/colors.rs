@@ -0,0 +1,117 @@
+// Colour schemes for flows, shared by the window (Gfx) and the terminal (Board::render).
+// Tables shorter than the number of flows are cycled, each further pass a shade darker.
+
+use hsv::hsv_to_rgb;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Scheme {
+    ///Hues spread around the colour wheel by a golden-ratio-like step
+    #[default]
+    Spectrum,
+    ///The colours and order of the Flow Free app
+    Classic,
+    ///Kelly's colours of maximum contrast
+    HighContrast,
+    ///Okabe-Ito, distinguishable with deuteranopia and protanopia
+    ColorBlind,
+}
+
+impl Scheme {
+    pub fn next(self) -> Self {
+        match self {
+            Scheme::Spectrum => Scheme::Classic,
+            Scheme::Classic => Scheme::HighContrast,
+            Scheme::HighContrast => Scheme::ColorBlind,
+            Scheme::ColorBlind => Scheme::Spectrum,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scheme::Spectrum => "spectrum",
+            Scheme::Classic => "classic",
+            Scheme::HighContrast => "high contrast",
+            Scheme::ColorBlind => "colour-blind safe",
+        }
+    }
+
+    pub fn rgb(self, color: u8, n_colors: usize) -> (u8, u8, u8) {
+        let table = match self {
+            Scheme::Spectrum => {
+                let hue = color as f64 / n_colors.max(1) as f64;
+                return hsv_to_rgb(
+                    (2.85419 * hue % 1.0) * 360.0,
+                    1.0,
+                    if hue > 0.5 { 0.8 } else { 1.0 },
+                );
+            }
+            Scheme::Classic => CLASSIC,
+            Scheme::HighContrast => HIGH_CONTRAST,
+            Scheme::ColorBlind => COLOR_BLIND,
+        };
+        let (r, g, b) = table[color as usize % table.len()];
+        let shade = (1.0 - 0.3 * (color as usize / table.len()) as f64).max(0.3);
+        (
+            (r as f64 * shade) as u8,
+            (g as f64 * shade) as u8,
+            (b as f64 * shade) as u8,
+        )
+    }
+}
+
+// As listed by mzucker's flow solver
+const CLASSIC: &[(u8, u8, u8)] = &[
+    (255, 0, 0),     // Red
+    (0, 0, 255),     // Blue
+    (238, 238, 0),   // Yellow
+    (0, 129, 0),     // Green
+    (255, 128, 0),   // Orange
+    (0, 255, 255),   // Cyan
+    (255, 0, 255),   // Magenta
+    (165, 42, 42),   // Maroon
+    (128, 0, 128),   // Purple
+    (166, 166, 166), // Gray
+    (255, 255, 255), // White
+    (0, 255, 0),     // Bright green
+    (189, 183, 107), // Tan
+    (0, 0, 139),     // Dark blue
+    (0, 128, 128),   // Dark cyan
+    (255, 20, 147),  // Pink
+];
+
+// Kelly's list without black, which would vanish against the background
+const HIGH_CONTRAST: &[(u8, u8, u8)] = &[
+    (243, 195, 0),   // Vivid yellow
+    (135, 86, 146),  // Strong purple
+    (243, 132, 0),   // Vivid orange
+    (161, 202, 241), // Very light blue
+    (190, 0, 50),    // Vivid red
+    (194, 178, 128), // Grayish yellow
+    (132, 132, 130), // Medium gray
+    (0, 136, 86),    // Vivid green
+    (230, 143, 172), // Strong purplish pink
+    (0, 103, 165),   // Strong blue
+    (249, 147, 121), // Strong yellowish pink
+    (96, 78, 151),   // Strong violet
+    (246, 166, 0),   // Vivid orange yellow
+    (179, 68, 108),  // Strong purplish red
+    (220, 211, 0),   // Vivid greenish yellow
+    (136, 45, 23),   // Strong reddish brown
+    (141, 182, 0),   // Vivid yellowish green
+    (101, 69, 34),   // Deep yellowish brown
+    (226, 88, 34),   // Vivid reddish orange
+    (43, 61, 38),    // Dark olive green
+    (242, 243, 244), // White
+];
+
+// Okabe-Ito with white standing in for black
+const COLOR_BLIND: &[(u8, u8, u8)] = &[
+    (230, 159, 0),   // Orange
+    (86, 180, 233),  // Sky blue
+    (0, 158, 115),   // Bluish green
+    (240, 228, 66),  // Yellow
+    (0, 114, 178),   // Blue
+    (213, 94, 0),    // Vermillion
+    (204, 121, 167), // Reddish purple
+    (255, 255, 255), // White
+];
@@ -52,16 +52,25 @@ def steal_flow(url):
     color_tuples = [tuple(rgb) for rgb in flat_colors]
 
     unique_colors = sorted(set(color_tuples))
-    if len(unique_colors) > 26:
-        raise ValueError("More than 26 unique colors, can't map to single letters.")
+    if len(unique_colors) > 255:
+        raise ValueError("More than 255 unique colors, the solver can't hold them.")
 
-    color_to_letter = {color: chr(ord('a') + i) for i, color in enumerate(unique_colors)}
+    # Letters while they last, then the numeric format: space-separated colour numbers, heads marked with *
+    if len(unique_colors) <= 26:
+        color_to_letter = {color: chr(ord('a') + i) for i, color in enumerate(unique_colors)}
 
-    letters = []
+        letters = []
+        for i in range(len(color_tuples)):
+            letters.append(color_to_letter[color_tuples[i]].capitalize() if flat_heads[i] else color_to_letter[color_tuples[i]])
+        rows_of_letters = ["".join(letters[i * cols:(i + 1) * cols]) for i in range(rows)]
+        return "\n".join(rows_of_letters)
+
+    color_to_number = {color: str(i) for i, color in enumerate(unique_colors)}
+    numbers = []
     for i in range(len(color_tuples)):
-        letters.append(color_to_letter[color_tuples[i]].capitalize() if flat_heads[i] else color_to_letter[color_tuples[i]])
-    rows_of_letters = ["".join(letters[i * cols:(i + 1) * cols]) for i in range(rows)]
-    return "\n".join(rows_of_letters)
+        numbers.append(color_to_number[color_tuples[i]] + ("*" if flat_heads[i] else ""))
+    rows_of_numbers = [" ".join(numbers[i * cols:(i + 1) * cols]) for i in range(rows)]
+    return "\n".join(rows_of_numbers)
 
 
 if __name__ == "__main__":
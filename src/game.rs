@@ -1,12 +1,47 @@
+use std::time::{Duration, Instant};
+
+use crate::backend::{self, PuzzleSolver};
+use crate::background::{Background, SolverEvent, Task};
 use crate::board::{Board, Cell};
+use crate::heuristic::Heuristic;
 use crate::{COLS, ROWS};
 
+const GROW_EVERY: Duration = Duration::from_millis(40); // Between cells of a path being shown
+
 pub struct Game {
     board: Board,
+    solution: Board, // As loaded, for pointing out where the player went wrong
     dragging: bool,
     color: usize,
     finished: bool,
     flows: Vec<Flow>,
+    background: Option<Background>, // Checks the position after each drag; none in the terminal
+    solver: String,                 // Backend for the checks and solves, by name
+    heuristic: Heuristic,
+    solvable: Option<bool>, // None until the check answers, or if it gave up
+    checked: Option<u64>,   // Work reported so far by a check that is taking a while
+    reveal: Option<Reveal>, // What the solve running in the background will be shown for
+    no_solution: bool,      // The last solve found nothing to show
+    animation: Option<Animation>,
+}
+
+// What to do with a solution once the background solve finds it
+#[derive(Debug, Clone, Copy)]
+enum Reveal {
+    All,          // Replace every flow
+    Color(usize), // Finish one flow, keeping the rest
+}
+
+// Solution paths growing from their heads, one cell per GROW_EVERY. Input is ignored meanwhile.
+struct Animation {
+    paths: Vec<Growing>,
+    start: Instant,
+}
+
+struct Growing {
+    color: usize,
+    path: Vec<(usize, usize)>, // Head to head
+    from: usize,               // Cells shown at the start
 }
 
 #[derive(Debug, Clone)]
@@ -47,7 +82,7 @@ impl Flow {
             .expect("Cell not found");
         if self.complete {
             if idx < self.cells.len() / 2 {
-                self.cells = self.cells[idx..].to_vec().into_iter().rev().collect();
+                self.cells = self.cells[idx..].iter().copied().rev().collect();
             } else {
                 self.cells = self.cells[..idx + 1].to_vec();
             }
@@ -66,7 +101,7 @@ impl Flow {
             .expect("Cell not found");
         if self.complete {
             if idx < self.cells.len() / 2 {
-                self.cells = self.cells[idx + 1..].to_vec().into_iter().rev().collect();
+                self.cells = self.cells[idx + 1..].iter().copied().rev().collect();
             } else {
                 self.cells = self.cells[..idx].to_vec();
             }
@@ -76,6 +111,15 @@ impl Flow {
         self.complete = false;
     }
 
+    fn retract(&mut self) -> Option<(usize, usize)> {
+        if self.cells.len() < 2 {
+            return None;
+        }
+        self.cells.pop();
+        self.complete = false;
+        self.cells.last().copied()
+    }
+
     fn restart(&mut self) {
         self.cells = vec![*self.cells.last().unwrap()];
         self.complete = false;
@@ -84,19 +128,48 @@ impl Flow {
 
 impl Game {
     pub fn new(board_string: &str) -> Self {
-        let mut board = Board::load_board(board_string, ROWS, COLS);
+        let solution = Board::load_board(board_string, ROWS, COLS);
+        let mut board = solution.clone();
         board.strip();
         let num_colors = board.num_colors();
 
         Game {
             board,
+            solution,
             dragging: false,
             color: 0,
             finished: false,
             flows: vec![Flow::new(); num_colors],
+            background: None,
+            solver: "sat".to_string(),
+            heuristic: Heuristic::default(),
+            solvable: None,
+            checked: None,
+            reveal: None,
+            no_solution: false,
+            animation: None,
         }
     }
 
+    ///Checks whether the position can still be solved after each drag, reporting back through
+    ///`background`'s event loop
+    pub fn with_background(mut self, background: Background) -> Self {
+        self.background = Some(background);
+        self
+    }
+
+    ///Checks and solves with the named backend (see backend::NAMES) rather than SAT
+    pub fn with_solver(mut self, name: &str, heuristic: Heuristic) -> Self {
+        assert!(backend::NAMES.contains(&name), "Unknown solver");
+        self.solver = name.to_string();
+        self.heuristic = heuristic;
+        self
+    }
+
+    fn new_solver(&self) -> Box<dyn PuzzleSolver> {
+        backend::by_name(&self.solver, self.heuristic, 1).unwrap()
+    }
+
     pub fn clear_flows(&mut self) {
         self.flows = vec![Flow::new(); self.board.num_colors()];
     }
@@ -105,9 +178,9 @@ impl Game {
         &self.board
     }
 
-    // pub fn is_dragging(&self) -> bool {
-    //     self.dragging
-    // }
+    pub fn is_dragging(&self) -> bool {
+        self.dragging
+    }
 
     fn update_board(&mut self) {
         self.board.strip();
@@ -129,6 +202,11 @@ impl Game {
         self.finished
     }
 
+    ///True once every cell is filled without the puzzle being solved
+    pub fn is_stuck(&self) -> bool {
+        !self.finished && !self.dragging && self.board.is_full()
+    }
+
     pub fn update(&mut self) -> bool {
         if !self.finished && self.board.is_solved() {
             self.finished = true;
@@ -138,7 +216,7 @@ impl Game {
     }
 
     pub fn handle_mouse_press(&mut self, row: usize, col: usize) {
-        if self.finished {
+        if self.finished || self.animation.is_some() {
             return;
         }
 
@@ -156,8 +234,200 @@ impl Game {
     }
 
     pub fn handle_mouse_release(&mut self) {
+        if self.animation.is_some() {
+            return;
+        }
         self.dragging = false;
         self.update();
+        self.check_position();
+    }
+
+    // Starts solving the current position in the background, cancelling a check still running
+    fn check_position(&mut self) {
+        self.solvable = None;
+        self.checked = None;
+        self.no_solution = false;
+        let solver = self.new_solver();
+        let Some(background) = &mut self.background else {
+            return;
+        };
+        if self.finished {
+            background.cancel(Task::Check);
+        } else {
+            background.start(Task::Check, self.board.clone(), solver);
+        }
+    }
+
+    ///Takes in progress or an outcome from a background run
+    pub fn handle_solver_event(&mut self, event: SolverEvent) {
+        let Some(background) = &mut self.background else {
+            return;
+        };
+        if !background.accept(&event) {
+            return;
+        }
+        match event {
+            SolverEvent::Progress {
+                task: Task::Check,
+                work,
+                ..
+            } => self.checked = Some(work),
+            SolverEvent::Done {
+                task: Task::Check,
+                outcome,
+                ..
+            } => {
+                self.solvable = (!outcome.gave_up).then_some(outcome.solution.is_some());
+                self.checked = None;
+            }
+            SolverEvent::Progress {
+                task: Task::Solve, ..
+            } => {}
+            SolverEvent::Done {
+                task: Task::Solve,
+                outcome,
+                ..
+            } => {
+                let Some(reveal) = self.reveal.take() else {
+                    return;
+                };
+                match outcome.solution {
+                    Some(solution) => self.animate_solution(reveal, &solution),
+                    None => self.no_solution = true,
+                }
+            }
+        }
+    }
+
+    ///Solves the puzzle in the background, then draws its solution in place of the player's flows
+    pub fn show_solution(&mut self) {
+        let mut puzzle = self.board.clone();
+        puzzle.strip();
+        self.solve_for(Reveal::All, puzzle);
+    }
+
+    ///Solves the position in the background, then finishes the flow of the colour last picked up.
+    ///From a position with no solution it uses the puzzle's, cutting back any flows in the way.
+    pub fn auto_complete(&mut self) {
+        let mut board = self.board.clone();
+        if self.solvable == Some(false) {
+            board.strip();
+        }
+        self.solve_for(Reveal::Color(self.color), board);
+    }
+
+    fn solve_for(&mut self, reveal: Reveal, board: Board) {
+        if self.finished || self.dragging || self.animation.is_some() {
+            return;
+        }
+        let solver = self.new_solver();
+        let Some(background) = &mut self.background else {
+            return;
+        };
+        background.start(Task::Solve, board, solver);
+        self.reveal = Some(reveal);
+        self.no_solution = false;
+    }
+
+    pub fn is_solving(&self) -> bool {
+        self.reveal.is_some()
+    }
+
+    ///True if the last solve found no solution, until the position changes or another solve starts
+    pub fn found_no_solution(&self) -> bool {
+        self.no_solution
+    }
+
+    // Starts growing the solution's paths: every one for Reveal::All, or one colour from as much of
+    // it as the player has drawn already
+    fn animate_solution(&mut self, reveal: Reveal, solution: &Board) {
+        let paths = solution
+            .paths()
+            .expect("Solvers return boards with whole paths");
+        let paths = match reveal {
+            Reveal::All => {
+                self.clear_flows();
+                paths
+                    .into_iter()
+                    .map(|p| Growing {
+                        color: p.color as usize,
+                        path: p.cells,
+                        from: 1,
+                    })
+                    .collect()
+            }
+            Reveal::Color(color) => {
+                let Some(mut path) = paths
+                    .into_iter()
+                    .find(|p| p.color as usize == color)
+                    .map(|p| p.cells)
+                else {
+                    return;
+                };
+                let drawn = &self.flows[color].cells;
+                if drawn.first() == path.last() {
+                    path.reverse();
+                }
+                let kept = drawn.iter().zip(&path).take_while(|(a, b)| a == b).count();
+                vec![Growing {
+                    color,
+                    path,
+                    from: kept.max(1),
+                }]
+            }
+        };
+        self.solvable = None;
+        self.animation = Some(Animation {
+            paths,
+            start: Instant::now(),
+        });
+    }
+
+    ///Moves the solution animation on to where it should be by now
+    pub fn animate(&mut self) {
+        let Some(animation) = &self.animation else {
+            return;
+        };
+        let grown = (animation.start.elapsed().as_millis() / GROW_EVERY.as_millis()) as usize;
+        let mut done = true;
+        for Growing { color, path, from } in &animation.paths {
+            let shown = (from + grown).min(path.len());
+            for (row, col) in &path[..shown] {
+                if let Some(other) = (0..self.flows.len())
+                    .find(|k| k != color && self.flows[*k].cells.contains(&(*row, *col)))
+                {
+                    self.flows[other].cut_before(*row, *col);
+                }
+            }
+            self.flows[*color].cells = path[..shown].to_vec();
+            self.flows[*color].complete = shown == path.len();
+            done &= shown == path.len();
+        }
+        self.update_board();
+        if done {
+            self.animation = None;
+            self.update();
+            self.check_position();
+        }
+    }
+
+    ///Work done so far by a check that hasn't answered within a moment
+    pub fn checking(&self) -> Option<u64> {
+        self.checked
+    }
+
+    ///True once the background check has shown the flows drawn so far can't be part of any solution
+    pub fn is_doomed(&self) -> bool {
+        !self.dragging && self.solvable == Some(false)
+    }
+
+    ///The first cell, walking each flow out from its head, that differs from the stored solution
+    pub fn first_wrong_cell(&self) -> Option<(usize, usize)> {
+        self.flows
+            .iter()
+            .flat_map(|flow| flow.cells.iter())
+            .find(|(row, col)| self.board[(*row, *col)] != self.solution[(*row, *col)])
+            .copied()
     }
 
     pub fn handle_mouse_move(&mut self, row: usize, col: usize) {
@@ -187,23 +457,34 @@ impl Game {
             self.dragging = false;
         }
 
-        let neighbor_result = self.board.neighbor_head(row, col, self.color as u8);
-        if neighbor_result.is_some()
-            && !self.flows[self.color]
-                .cells
-                .contains(&neighbor_result.unwrap())
+        if let Some((head_row, head_col)) = self.board.neighbor_head(row, col, self.color as u8)
+            && !self.flows[self.color].cells.contains(&(head_row, head_col))
         {
-            self.flows[self.color].add_cell(neighbor_result.unwrap().0, neighbor_result.unwrap().1);
+            self.flows[self.color].add_cell(head_row, head_col);
             self.flows[self.color].set_complete();
         }
         self.update_board();
     }
 
+    ///Pulls the current flow back by one cell, returning its new tip
+    pub fn retract(&mut self) -> Option<(usize, usize)> {
+        if self.finished || self.animation.is_some() {
+            return None;
+        }
+        let tip = self.flows[self.color].retract();
+        self.update_board();
+        if !self.dragging {
+            self.check_position();
+        }
+        tip
+    }
+
     pub fn handle_right_click(&mut self) {
-        if self.finished {
+        if self.finished || self.animation.is_some() {
             return;
         }
         self.board.strip();
         self.clear_flows();
+        self.check_position();
     }
 }
@@ -3,17 +3,13 @@ use std::cmp::min;
 use pixels::{Pixels, SurfaceTexture};
 use winit::{
     dpi::PhysicalSize,
-    event_loop::EventLoop,
+    event_loop::{EventLoop, EventLoopBuilder},
     window::{Window, WindowBuilder},
 };
 
-// const COLORS: [&str; 7] = [
-//     "9a7bd2", "0000ff", "00ff00", "00ffff", "ff0000", "ff00ff", "ffff00",
-// ];
-
 use crate::board::Board;
 use crate::board::Cell;
-use hsv::{self, hsv_to_rgb};
+use crate::colors::Scheme;
 
 pub const PIXEL_SCALE: u32 = 40;
 const PPC: usize = 3;
@@ -24,11 +20,14 @@ pub struct Gfx {
     pixels: Pixels,
     pub width: u32,
     pub height: u32,
+    pub scheme: Scheme,
+    pub glyphs: bool, // Mark heads with a per-colour shape instead of a plain white centre
 }
 
 impl Gfx {
-    pub fn new(cols: u32, rows: u32) -> (Self, EventLoop<()>) {
-        let event_loop = EventLoop::new();
+    ///Opens the window, with an event loop carrying user events of type T
+    pub fn new<T: 'static>(cols: u32, rows: u32) -> (Self, EventLoop<T>) {
+        let event_loop = EventLoopBuilder::with_user_event().build();
         // physical window size = virtual size × scale
         let physical_size = PhysicalSize::new(
             cols * PIXEL_SCALE * PIXELS_PER_CELL,
@@ -60,6 +59,8 @@ impl Gfx {
                 pixels,
                 width: cols * PIXELS_PER_CELL,
                 height: rows * PIXELS_PER_CELL,
+                scheme: Scheme::default(),
+                glyphs: false,
             },
             event_loop,
         )
@@ -74,294 +75,169 @@ impl Gfx {
     }
 
     pub fn display(&mut self, board: &Board) {
-        let n_colors = board.num_colors() as u8;
-        let frame = self.pixels.frame_mut();
-
-        let W = self.width as usize;
-
-        let white = [255, 255, 255, 255];
-        let black = [0, 0, 0, 255];
-        frame.fill(0);
-
-        for i in 0..board.len() {
-            let c = board[i];
-            match c {
-                Cell::Empty {} => {}
-                _ => {
-                    let color;
-                    // if c.color() < 5 {
-                    let hue = (c.color() as f64 / n_colors as f64);
-                    let rgb = hsv_to_rgb(
-                        (2.85419 * hue % 1.0) * 360.0,
-                        1.0,
-                        if hue > 0.5 { 0.8 } else { 1.0 },
-                    );
-                    color = [rgb.0, rgb.1, rgb.2, 255];
-                    // } else {
-                    // let mut decoded = [0; 3];
-                    // hex::decode_to_slice(COLORS[(c.color()) as usize], &mut decoded)
-                    //     .expect("Decoding failed");
-                    // color = [decoded[0], decoded[1], decoded[2], 255];
-                    // // }
-
-                    let topleft = ((i * PPC) / W * PPC * W + (i * PPC) % W) * 4;
-
-                    for i in 0..PPC - 2 {
-                        frame[topleft + 4 + (W) * (i + 1) * 4
-                            ..topleft + (W) * (i + 1) * 4 + 4 + 4 * (PPC - 2)]
-                            .copy_from_slice(&color.repeat(PPC - 2));
-                    }
-
-                    let orientation = board.orientation(i);
-                    if c.is_head() {
-                        frame[topleft..topleft + 4 * PPC].copy_from_slice(&color.repeat(PPC));
-
-                        for i in 0..PPC {
-                            frame[topleft + (W) * i * 4..topleft + (W) * i * 4 + 4 * PPC]
-                                .copy_from_slice(&color.repeat(PPC));
-                        }
-
-                        frame[topleft + (PPC / 2) * 4 + (W) * (PPC / 2) * 4
-                            ..topleft + (PPC / 2) * 4 + (W) * (PPC / 2) * 4 + 4]
-                            .copy_from_slice(&white);
-
-                        // if orientation == 0 {
-                        //     for i in 0..PPC {
-                        //         frame[topleft + (W) * (i) * 4..topleft + (W) * (i) * 4 + 4 * (PPC)]
-                        //             .copy_from_slice(&black.repeat(PPC));
-                        //     }
-
-                        //     frame[topleft + 4..topleft + 4 + 4 * (PPC - 2)]
-                        //         .copy_from_slice(&color.repeat(PPC - 2));
-                        //     for i in 0..PPC - 2 {
-                        //         frame[topleft + (W) * (i + 1) * 4..topleft + (W) * (i + 1) * 4 + 4]
-                        //             .copy_from_slice(&color);
-                        //     }
-
-                        //     frame[topleft + 4 + (W) * (PPC - 1) * 4
-                        //         ..topleft + 4 + (W) * (PPC - 1) * 4 + 4 * (PPC - 2)]
-                        //         .copy_from_slice(&color.repeat(PPC - 2));
-
-                        //     for i in 0..PPC - 2 {
-                        //         frame[topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4
-                        //             ..topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4 + 4]
-                        //             .copy_from_slice(&color);
-                        //     }
-                        // }
-                    }
-
-                    if orientation & 1 == 1 {
-                        frame[topleft + 4..topleft + 4 + 4 * (PPC - 2)]
-                            .copy_from_slice(&color.repeat(PPC - 2));
-                    }
-
-                    if orientation & 2 == 2 {
-                        for i in 0..PPC - 2 {
-                            frame[topleft + (W) * (i + 1) * 4..topleft + (W) * (i + 1) * 4 + 4]
-                                .copy_from_slice(&color);
-                        }
-                    }
+        self.draw(board, Palette::Play, 1.0, &[]);
+    }
 
-                    if orientation & 4 == 4 {
-                        frame[topleft + 4 + (W) * (PPC - 1) * 4
-                            ..topleft + 4 + (W) * (PPC - 1) * 4 + 4 * (PPC - 2)]
-                            .copy_from_slice(&color.repeat(PPC - 2));
-                    }
+    ///Draws the board with the given path cells washed out towards white
+    pub fn highlight_display(&mut self, board: &Board, highlight: &[(usize, usize)]) {
+        self.draw(board, Palette::Play, 1.0, highlight);
+    }
 
-                    if orientation & 8 == 8 {
-                        for i in 0..PPC - 2 {
-                            frame[topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4
-                                ..topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4 + 4]
-                                .copy_from_slice(&color);
-                        }
-                    }
-                }
-            }
+    ///Marks the corners of a cell in white, for the keyboard cursor
+    pub fn draw_cursor(&mut self, row: usize, col: usize) {
+        let W = self.width as usize;
+        let frame = self.pixels.frame_mut();
+        let (x, y) = (col * PPC, row * PPC);
+        for (dx, dy) in [(0, 0), (PPC - 1, 0), (0, PPC - 1), (PPC - 1, PPC - 1)] {
+            fill(frame, W, x + dx, y + dy, 1, 1, WHITE);
         }
     }
 
     pub fn success_display(&mut self, board: &Board) {
-        let frame = self.pixels.frame_mut();
+        self.draw(board, Palette::Success, 1.0, &[]);
+    }
 
-        let W = self.width as usize;
+    pub fn fail_display(&mut self, board: &Board) {
+        self.draw(board, Palette::Fail, 1.0, &[]);
+    }
+
+    ///Draws the board part way through fading from the play colours into `palette`, with `t` in 0..=1
+    pub fn transition_display(&mut self, board: &Board, palette: Palette, t: f64) {
+        self.draw(board, palette, t.clamp(0.0, 1.0), &[]);
+    }
 
-        let white = [255, 255, 255, 255];
-        let black = [0, 0, 0, 255];
+    fn draw(&mut self, board: &Board, palette: Palette, t: f64, highlight: &[(usize, usize)]) {
+        let n_colors = board.num_colors();
+        let (scheme, glyphs) = (self.scheme, self.glyphs);
+        let W = self.width as usize;
+        let frame = self.pixels.frame_mut();
         frame.fill(0);
 
+        // Play marks every head with a white centre; the end screens outline heads with no flow instead.
+        let outline_heads = palette != Palette::Play && t >= 1.0;
+
         for i in 0..board.len() {
             let c = board[i];
-            match c {
-                Cell::Empty {} => {}
-                _ => {
-                    let color = [255, 255, 255, 255];
-
-                    let topleft = ((i * PPC) / W * PPC * W + (i * PPC) % W) * 4;
-
-                    for i in 0..PPC - 2 {
-                        frame[topleft + 4 + (W) * (i + 1) * 4
-                            ..topleft + (W) * (i + 1) * 4 + 4 + 4 * (PPC - 2)]
-                            .copy_from_slice(&color.repeat(PPC - 2));
-                    }
-
-                    let orientation = board.orientation(i);
-                    if c.is_head() {
-                        frame[topleft..topleft + 4 * PPC].copy_from_slice(&color.repeat(PPC));
-
-                        for i in 0..PPC {
-                            frame[topleft + (W) * i * 4..topleft + (W) * i * 4 + 4 * PPC]
-                                .copy_from_slice(&color.repeat(PPC));
-                        }
-
-                        // frame[topleft + (PPC / 2) * 4 + (W) * (PPC / 2) * 4
-                        //     ..topleft + (PPC / 2) * 4 + (W) * (PPC / 2) * 4 + 4]
-                        //     .copy_from_slice(&white);
-
-                        if orientation == 0 {
-                            for i in 0..PPC {
-                                frame[topleft + (W) * (i) * 4..topleft + (W) * (i) * 4 + 4 * (PPC)]
-                                    .copy_from_slice(&black.repeat(PPC));
-                            }
-
-                            frame[topleft + 4..topleft + 4 + 4 * (PPC - 2)]
-                                .copy_from_slice(&color.repeat(PPC - 2));
-                            for i in 0..PPC - 2 {
-                                frame[topleft + (W) * (i + 1) * 4..topleft + (W) * (i + 1) * 4 + 4]
-                                    .copy_from_slice(&color);
-                            }
-
-                            frame[topleft + 4 + (W) * (PPC - 1) * 4
-                                ..topleft + 4 + (W) * (PPC - 1) * 4 + 4 * (PPC - 2)]
-                                .copy_from_slice(&color.repeat(PPC - 2));
-
-                            for i in 0..PPC - 2 {
-                                frame[topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4
-                                    ..topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4 + 4]
-                                    .copy_from_slice(&color);
-                            }
-                        }
-                    }
-
-                    if orientation & 1 == 1 {
-                        frame[topleft + 4..topleft + 4 + 4 * (PPC - 2)]
-                            .copy_from_slice(&color.repeat(PPC - 2));
-                    }
+            if c.is_empty() {
+                continue;
+            }
+            let (row, col) = board.inverse_ind(i);
+            let mut color = lerp(
+                Palette::Play.color(c, n_colors, scheme),
+                palette.color(c, n_colors, scheme),
+                t,
+            );
+            if highlight.contains(&(row, col)) {
+                color = lerp(color, WHITE, 0.5);
+            }
+            let (x, y) = (col * PPC, row * PPC);
+            let orientation = board.orientation(i);
+
+            if c.is_head() && outline_heads && orientation == 0 {
+                fill(frame, W, x, y, PPC, PPC, BLACK);
+                fill(frame, W, x + 1, y, PPC - 2, 1, color);
+                fill(frame, W, x, y + 1, 1, PPC - 2, color);
+                fill(frame, W, x + 1, y + PPC - 1, PPC - 2, 1, color);
+                fill(frame, W, x + PPC - 1, y + 1, 1, PPC - 2, color);
+                continue;
+            }
 
-                    if orientation & 2 == 2 {
-                        for i in 0..PPC - 2 {
-                            frame[topleft + (W) * (i + 1) * 4..topleft + (W) * (i + 1) * 4 + 4]
-                                .copy_from_slice(&color);
-                        }
-                    }
+            if c.is_head() {
+                fill(frame, W, x, y, PPC, PPC, color);
+                if !outline_heads && !glyphs {
+                    fill(frame, W, x + PPC / 2, y + PPC / 2, 1, 1, WHITE);
+                }
+            } else {
+                fill(frame, W, x + 1, y + 1, PPC - 2, PPC - 2, color);
+            }
 
-                    if orientation & 4 == 4 {
-                        frame[topleft + 4 + (W) * (PPC - 1) * 4
-                            ..topleft + 4 + (W) * (PPC - 1) * 4 + 4 * (PPC - 2)]
-                            .copy_from_slice(&color.repeat(PPC - 2));
-                    }
+            if orientation & 1 == 1 {
+                fill(frame, W, x + 1, y, PPC - 2, 1, color);
+            }
+            if orientation & 2 == 2 {
+                fill(frame, W, x, y + 1, 1, PPC - 2, color);
+            }
+            if orientation & 4 == 4 {
+                fill(frame, W, x + 1, y + PPC - 1, PPC - 2, 1, color);
+            }
+            if orientation & 8 == 8 {
+                fill(frame, W, x + PPC - 1, y + 1, 1, PPC - 2, color);
+            }
 
-                    if orientation & 8 == 8 {
-                        for i in 0..PPC - 2 {
-                            frame[topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4
-                                ..topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4 + 4]
-                                .copy_from_slice(&color);
-                        }
+            if c.is_head() && !outline_heads && glyphs {
+                let ink = if luminance(color) > 140.0 {
+                    BLACK
+                } else {
+                    WHITE
+                };
+                let shape = GLYPHS[c.color() as usize % GLYPHS.len()];
+                for bit in 0..9 {
+                    if shape & (1 << (8 - bit)) != 0 {
+                        fill(frame, W, x + bit % 3, y + bit / 3, 1, 1, ink);
                     }
                 }
             }
         }
     }
+}
 
-    pub fn fail_display(&mut self, board: &Board) {
-        let frame = self.pixels.frame_mut();
-
-        let W = self.width as usize;
-
-        let white = [255, 255, 255, 255];
-        let black = [0, 0, 0, 255];
-        frame.fill(0);
-
-        for i in 0..board.len() {
-            let c = board[i];
-            match c {
-                Cell::Empty {} => {}
-                _ => {
-                    let color = [128, 0, 0, 255];
-
-                    let topleft = ((i * PPC) / W * PPC * W + (i * PPC) % W) * 4;
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+const BLACK: [u8; 4] = [0, 0, 0, 255];
 
-                    for i in 0..PPC - 2 {
-                        frame[topleft + 4 + (W) * (i + 1) * 4
-                            ..topleft + (W) * (i + 1) * 4 + 4 + 4 * (PPC - 2)]
-                            .copy_from_slice(&color.repeat(PPC - 2));
-                    }
-
-                    let orientation = board.orientation(i);
-                    if c.is_head() {
-                        frame[topleft..topleft + 4 * PPC].copy_from_slice(&color.repeat(PPC));
-
-                        for i in 0..PPC {
-                            frame[topleft + (W) * i * 4..topleft + (W) * i * 4 + 4 * PPC]
-                                .copy_from_slice(&color.repeat(PPC));
-                        }
-
-                        // frame[topleft + (PPC / 2) * 4 + (W) * (PPC / 2) * 4
-                        //     ..topleft + (PPC / 2) * 4 + (W) * (PPC / 2) * 4 + 4]
-                        //     .copy_from_slice(&white);
-
-                        if orientation == 0 {
-                            for i in 0..PPC {
-                                frame[topleft + (W) * (i) * 4..topleft + (W) * (i) * 4 + 4 * (PPC)]
-                                    .copy_from_slice(&black.repeat(PPC));
-                            }
-
-                            frame[topleft + 4..topleft + 4 + 4 * (PPC - 2)]
-                                .copy_from_slice(&color.repeat(PPC - 2));
-                            for i in 0..PPC - 2 {
-                                frame[topleft + (W) * (i + 1) * 4..topleft + (W) * (i + 1) * 4 + 4]
-                                    .copy_from_slice(&color);
-                            }
-
-                            frame[topleft + 4 + (W) * (PPC - 1) * 4
-                                ..topleft + 4 + (W) * (PPC - 1) * 4 + 4 * (PPC - 2)]
-                                .copy_from_slice(&color.repeat(PPC - 2));
-
-                            for i in 0..PPC - 2 {
-                                frame[topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4
-                                    ..topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4 + 4]
-                                    .copy_from_slice(&color);
-                            }
-                        }
-                    }
+///Colouring scheme used by Gfx::draw
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Palette {
+    Play,
+    Success,
+    Fail,
+}
 
-                    if orientation & 1 == 1 {
-                        frame[topleft + 4..topleft + 4 + 4 * (PPC - 2)]
-                            .copy_from_slice(&color.repeat(PPC - 2));
-                    }
+impl Palette {
+    fn color(self, c: Cell, n_colors: usize, scheme: Scheme) -> [u8; 4] {
+        match self {
+            Palette::Play => {
+                let rgb = scheme.rgb(c.color(), n_colors);
+                [rgb.0, rgb.1, rgb.2, 255]
+            }
+            Palette::Success => WHITE,
+            Palette::Fail => [128, 0, 0, 255],
+        }
+    }
+}
 
-                    if orientation & 2 == 2 {
-                        for i in 0..PPC - 2 {
-                            frame[topleft + (W) * (i + 1) * 4..topleft + (W) * (i + 1) * 4 + 4]
-                                .copy_from_slice(&color);
-                        }
-                    }
+// 3x3 head markings, top row in the high bits, so colours can be told apart by shape alone
+const GLYPHS: [u16; 12] = [
+    0b000_010_000, // Dot
+    0b010_111_010, // Plus
+    0b101_010_101, // Cross
+    0b000_111_000, // Horizontal bar
+    0b010_010_010, // Vertical bar
+    0b100_010_001, // Backslash
+    0b001_010_100, // Slash
+    0b101_000_101, // Corners
+    0b111_000_000, // Top bar
+    0b000_000_111, // Bottom bar
+    0b100_100_100, // Left bar
+    0b001_001_001, // Right bar
+];
+
+fn luminance(color: [u8; 4]) -> f64 {
+    0.299 * color[0] as f64 + 0.587 * color[1] as f64 + 0.114 * color[2] as f64
+}
 
-                    if orientation & 4 == 4 {
-                        frame[topleft + 4 + (W) * (PPC - 1) * 4
-                            ..topleft + 4 + (W) * (PPC - 1) * 4 + 4 * (PPC - 2)]
-                            .copy_from_slice(&color.repeat(PPC - 2));
-                    }
+fn lerp(from: [u8; 4], to: [u8; 4], t: f64) -> [u8; 4] {
+    let mut out = [0; 4];
+    for i in 0..4 {
+        out[i] = (from[i] as f64 + (to[i] as f64 - from[i] as f64) * t).round() as u8;
+    }
+    out
+}
 
-                    if orientation & 8 == 8 {
-                        for i in 0..PPC - 2 {
-                            frame[topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4
-                                ..topleft + 4 * (PPC - 1) + (W) * (i + 1) * 4 + 4]
-                                .copy_from_slice(&color);
-                        }
-                    }
-                }
-            }
-        }
+///Fills a w by h rectangle of logical pixels with its top left corner at (x, y)
+fn fill(frame: &mut [u8], W: usize, x: usize, y: usize, w: usize, h: usize, color: [u8; 4]) {
+    for row in y..y + h {
+        let start = (row * W + x) * 4;
+        frame[start..start + w * 4].copy_from_slice(&color.repeat(w));
     }
 }
 
@@ -0,0 +1,76 @@
+// Heuristics for choosing which flow tip the solver branches on, and in which order to try its moves.
+// Each candidate tip gets a score and the lowest score wins; ties go to the earlier flow.
+
+use crate::board::Board;
+use crate::solver::Coord;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Heuristic {
+    ///The tip with the fewest moves, the first one found if several tie
+    #[default]
+    First,
+    ///The tip with the fewest moves, then the flow whose two tips have the fewest between them
+    FewestOptions,
+    ///The flow whose tips are closest together, then the tip with the fewest moves
+    Shortest,
+    ///The tip with a move into the most enclosed cell, next to walls and other flows, then the tip
+    ///with the fewest moves
+    Hugging,
+}
+
+impl Heuristic {
+    pub const ALL: [Heuristic; 4] = [
+        Heuristic::First,
+        Heuristic::FewestOptions,
+        Heuristic::Shortest,
+        Heuristic::Hugging,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Heuristic::First => "first",
+            Heuristic::FewestOptions => "fewest",
+            Heuristic::Shortest => "shortest",
+            Heuristic::Hugging => "hugging",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|h| h.name() == name)
+    }
+
+    ///Score for branching on a tip with the given moves, compared first by the first number;
+    ///`tips` are both ends of its flow, and `other_moves` is how many moves the other end has
+    pub fn score(
+        self,
+        board: &Board,
+        tips: [Coord; 2],
+        moves: &[Coord],
+        other_moves: usize,
+    ) -> (usize, usize) {
+        match self {
+            Heuristic::First => (moves.len(), 0),
+            Heuristic::FewestOptions => (moves.len(), moves.len() + other_moves),
+            Heuristic::Shortest => (
+                tips[0].0.abs_diff(tips[1].0) + tips[0].1.abs_diff(tips[1].1),
+                moves.len(),
+            ),
+            Heuristic::Hugging => (
+                moves.iter().map(|m| openness(board, *m)).min().unwrap_or(0),
+                moves.len(),
+            ),
+        }
+    }
+
+    ///Puts the moves from one tip in the order they should be tried
+    pub fn order(self, board: &Board, moves: &mut [Coord]) {
+        if self == Heuristic::Hugging {
+            moves.sort_by_key(|m| openness(board, *m));
+        }
+    }
+}
+
+// Number of empty cells around a cell
+fn openness(board: &Board, c: Coord) -> usize {
+    board.empty_neighbors(c.0, c.1).iter().flatten().count()
+}
@@ -1,20 +1,36 @@
 #![allow(unused)]
 #![allow(non_snake_case)]
+mod backend;
+mod background;
+mod bench;
+mod best_first;
+mod bitboard;
 mod board;
+mod cnf;
+mod colors;
 mod game;
 mod gfx;
+mod heuristic;
+mod parallel;
+mod sat;
 mod solver;
 mod solver_stack;
+mod tui;
+mod watch;
+mod zobrist;
 
+use crate::background::{Background, SolverEvent};
 use crate::board::Board;
 use crate::game::Game;
+use crate::gfx::Palette;
+use crate::heuristic::Heuristic;
 use crate::solver_stack::SolverStack;
 use std::time::Duration;
 use std::{thread, time::Instant};
 
 use std::fs;
 use winit::{
-    event::{ElementState, Event, MouseButton, WindowEvent},
+    event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent},
     event_loop::ControlFlow,
 };
 
@@ -26,46 +42,273 @@ const NUM_PUZZLES: u32 = 150;
 
 const SOLVING: bool = true;
 
-fn initialize(n: u32) -> Game {
+fn load_puzzle(n: u32) -> String {
     let file_string = format!("flows/{}x{}_{}.txt", COLS, ROWS, n);
-    let board_string: String = fs::read_to_string(file_string).unwrap();
-    // let mut board = Board::load_board(&board_string, ROWS, COLS);
-    // board.strip();
-    // solver_stack::SolverStack::new(solver::Solver::new(&board))
-    Game::new(&board_string)
+    fs::read_to_string(file_string).unwrap()
+}
+
+fn initialize(n: u32) -> Game {
+    Game::new(&load_puzzle(n))
 }
 
+enum Screen {
+    Playing,
+    Complete { since: Instant },
+    Failed { since: Instant },
+    Menu,
+}
+
+const TRANSITION: Duration = Duration::from_millis(750);
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("watch") => watch::run(arg_or(&args, 1, 1), arg_or(&args, 2, 1)),
+        Some("tui") => tui::run(arg_or(&args, 1, 1)).unwrap(),
+        Some("bench") => {
+            let (solver, heuristic) = strategy(args.get(3));
+            bench::run(
+                args.get(1).map_or("15x15", String::as_str),
+                arg_or(&args, 2, NUM_PUZZLES) as usize,
+                solver,
+                heuristic,
+                arg_or(&args, 4, 1) as usize,
+                arg_or(&args, 5, 1) as usize,
+            )
+        }
+        Some("solve") => {
+            let (solver, heuristic) = strategy(args.get(2));
+            backend::solve_file(
+                args.get(1).expect("Usage: flow4 solve <board> [strategy]"),
+                solver,
+                heuristic,
+            )
+        }
+        Some("cnf") => cnf::export(
+            args.get(1).expect("Usage: flow4 cnf <puzzle> [out]"),
+            args.get(2).map(String::as_str),
+        ),
+        Some("decode") => cnf::import(
+            args.get(1).expect("Usage: flow4 decode <puzzle> <model>"),
+            args.get(2).expect("Usage: flow4 decode <puzzle> <model>"),
+        ),
+        Some("check") => bench::check(
+            args.get(1).map_or("all", String::as_str),
+            arg_or(&args, 2, NUM_PUZZLES) as usize,
+            args.get(3)
+                .map(|name| Heuristic::from_name(name).expect("Unknown heuristic"))
+                .unwrap_or_default(),
+        ),
+        Some("play") if args.len() > 1 => {
+            let (solver, heuristic) = strategy(args.get(1));
+            play(solver, heuristic)
+        }
+        _ => play("sat", Heuristic::default()),
+    }
+}
+
+// "solver:heuristic", a solver, or a heuristic for the search with that heuristic
+fn strategy(arg: Option<&String>) -> (&str, Heuristic) {
+    let strategy = arg.map_or("search", String::as_str);
+    let (solver, heuristic) = match strategy.split_once(':') {
+        Some((solver, heuristic)) => (solver, Some(heuristic)),
+        None if Heuristic::from_name(strategy).is_some() => ("search", Some(strategy)),
+        None => (strategy, None),
+    };
+    let heuristic = heuristic
+        .map(|name| Heuristic::from_name(name).expect("Unknown heuristic"))
+        .unwrap_or_default();
+    (solver, heuristic)
+}
+
+fn arg_or(args: &[String], i: usize, default: u32) -> u32 {
+    args.get(i).and_then(|a| a.parse().ok()).unwrap_or(default)
+}
+
+fn play(solver: &str, heuristic: Heuristic) {
     let mut n = 1;
-    let mut game = initialize(n);
+    let (mut gfx, event_loop) = gfx::Gfx::new::<SolverEvent>(ROWS as u32, COLS as u32);
+    let proxy = event_loop.create_proxy();
+    let solver = solver.to_string();
+    let new_game = move |n| {
+        initialize(n)
+            .with_solver(&solver, heuristic)
+            .with_background(Background::new(proxy.clone()))
+    };
+    let mut game = new_game(n);
+    let mut screen = Screen::Playing;
     let mut col = 0;
     let mut row = 0;
-    let (mut gfx, event_loop) = gfx::Gfx::new(ROWS as u32, COLS as u32);
+    let mut keyboard = false;
+    let mut shown = status(&game); // What the title currently says about the position
+    set_title(&gfx, &screen, n, &game);
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
 
         if let Event::MainEventsCleared = event {
-            // let emu = chip8.lock().unwrap();
-            gfx.display(game.get_board());
+            match screen {
+                Screen::Playing => {
+                    game.animate();
+                    if status(&game) != shown {
+                        shown = status(&game);
+                        set_title(&gfx, &screen, n, &game);
+                    }
+                    let wrong: Vec<_> = game
+                        .first_wrong_cell()
+                        .filter(|_| game.is_doomed())
+                        .into_iter()
+                        .collect();
+                    gfx.highlight_display(game.get_board(), &wrong);
+                    if keyboard {
+                        gfx.draw_cursor(row, col);
+                    }
+                }
+                Screen::Menu => gfx.display(game.get_board()),
+                Screen::Complete { since } => gfx.transition_display(
+                    game.get_board(),
+                    Palette::Success,
+                    since.elapsed().as_secs_f64() / TRANSITION.as_secs_f64(),
+                ),
+                Screen::Failed { since } => gfx.transition_display(
+                    game.get_board(),
+                    Palette::Fail,
+                    since.elapsed().as_secs_f64() / TRANSITION.as_secs_f64(),
+                ),
+            }
             gfx.render();
 
-            if game.is_finished() {
-                println!("Level {} complete!", n);
-                n += 1;
-                *control_flow = ControlFlow::WaitUntil(
-                    Instant::now().checked_add(Duration::from_secs(3)).unwrap(),
-                );
-                game = initialize(n);
+            if let Screen::Playing = screen {
+                if game.is_finished() {
+                    println!("Level {} complete!", n);
+                    screen = Screen::Complete {
+                        since: Instant::now(),
+                    };
+                    set_title(&gfx, &screen, n, &game);
+                } else if game.is_stuck() {
+                    println!("Level {} failed!", n);
+                    screen = Screen::Failed {
+                        since: Instant::now(),
+                    };
+                    set_title(&gfx, &screen, n, &game);
+                }
             }
         }
 
-        match &event {
-            Event::WindowEvent { window_id, event } => {
-                match event {
-                    WindowEvent::CloseRequested => {
-                        *control_flow = ControlFlow::Exit;
+        if let Event::UserEvent(event) = event {
+            game.handle_solver_event(event);
+            return;
+        }
+
+        if let Event::WindowEvent { event, .. } = &event {
+            match event {
+                WindowEvent::CloseRequested => {
+                    *control_flow = ControlFlow::Exit;
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } => {
+                    let next = match (&screen, key) {
+                        (
+                            Screen::Playing,
+                            VirtualKeyCode::Up
+                            | VirtualKeyCode::Down
+                            | VirtualKeyCode::Left
+                            | VirtualKeyCode::Right,
+                        ) => {
+                            keyboard = true;
+                            let (old_row, old_col) = (row, col);
+                            match key {
+                                VirtualKeyCode::Up => row = row.saturating_sub(1),
+                                VirtualKeyCode::Down => row = (row + 1).min(ROWS - 1),
+                                VirtualKeyCode::Left => col = col.saturating_sub(1),
+                                _ => col = (col + 1).min(COLS - 1),
+                            }
+                            if (row, col) != (old_row, old_col) {
+                                game.handle_mouse_move(row, col);
+                            }
+                            None
+                        }
+                        (Screen::Playing, VirtualKeyCode::Space) => {
+                            keyboard = true;
+                            if game.is_dragging() {
+                                game.handle_mouse_release();
+                            } else {
+                                game.handle_mouse_press(row, col);
+                            }
+                            None
+                        }
+                        (Screen::Playing, VirtualKeyCode::Back) => {
+                            if let Some((tip_row, tip_col)) = game.retract() {
+                                (row, col) = (tip_row, tip_col);
+                            }
+                            None
+                        }
+                        (Screen::Playing | Screen::Menu, VirtualKeyCode::C) => {
+                            gfx.scheme = gfx.scheme.next();
+                            set_title(&gfx, &screen, n, &game);
+                            None
+                        }
+                        (Screen::Playing | Screen::Menu, VirtualKeyCode::G) => {
+                            gfx.glyphs = !gfx.glyphs;
+                            None
+                        }
+                        (Screen::Playing, VirtualKeyCode::S) => {
+                            game.show_solution();
+                            None
+                        }
+                        (Screen::Playing, VirtualKeyCode::A) => {
+                            game.auto_complete();
+                            None
+                        }
+                        (Screen::Playing, VirtualKeyCode::R) => {
+                            game.handle_right_click();
+                            None
+                        }
+                        (Screen::Playing, VirtualKeyCode::P) => {
+                            n = (n + NUM_PUZZLES - 2) % NUM_PUZZLES + 1;
+                            Some(Screen::Playing)
+                        }
+                        (
+                            Screen::Playing | Screen::Complete { .. } | Screen::Failed { .. },
+                            VirtualKeyCode::N,
+                        ) => {
+                            n = n % NUM_PUZZLES + 1;
+                            Some(Screen::Playing)
+                        }
+                        (Screen::Complete { .. } | Screen::Failed { .. }, VirtualKeyCode::R) => {
+                            Some(Screen::Playing)
+                        }
+                        (Screen::Complete { .. } | Screen::Failed { .. }, VirtualKeyCode::M) => {
+                            Some(Screen::Menu)
+                        }
+                        (Screen::Menu, VirtualKeyCode::Left) => {
+                            n = (n + NUM_PUZZLES - 2) % NUM_PUZZLES + 1;
+                            Some(Screen::Menu)
+                        }
+                        (Screen::Menu, VirtualKeyCode::Right) => {
+                            n = n % NUM_PUZZLES + 1;
+                            Some(Screen::Menu)
+                        }
+                        (Screen::Menu, VirtualKeyCode::Return) => Some(Screen::Playing),
+                        _ => None,
+                    };
+                    if let Some(next) = next {
+                        screen = next;
+                        game = new_game(n);
+                        set_title(&gfx, &screen, n, &game);
+                    }
+                }
+                WindowEvent::MouseInput { state, button, .. } => {
+                    if !matches!(screen, Screen::Playing) {
+                        return;
                     }
-                    WindowEvent::MouseInput { state, button, .. } => match (state, button) {
+                    match (state, button) {
                         (ElementState::Pressed, MouseButton::Left) => {
                             game.handle_mouse_press(row, col);
                         }
@@ -76,40 +319,36 @@ fn main() {
                             game.handle_right_click();
                         }
                         _ => {}
-                    },
-
-                    // Mouse movement
-                    WindowEvent::CursorMoved { position, .. } => {
-                        if position.x as f64 / gfx::PIXEL_SCALE as f64 > gfx.width as f64
-                            || position.y as f64 / gfx::PIXEL_SCALE as f64 > gfx.height as f64
-                        {
-                            // println!(
-                            //     "Position: {:?} Limits: {:?}",
-                            //     position,
-                            //     (gfx.width, gfx.height)
-                            // );
-                            return;
-                        }
-                        let (new_col, new_row) = window_to_grid(
-                            position.x,
-                            position.y,
-                            gfx::PIXEL_SCALE,
-                            gfx::PIXELS_PER_CELL,
-                        );
-
-                        if new_row != row || new_col != col {
-                            row = new_row;
-                            col = new_col;
+                    }
+                }
+
+                // Mouse movement
+                WindowEvent::CursorMoved { position, .. } => {
+                    if position.x / gfx::PIXEL_SCALE as f64 > gfx.width as f64
+                        || position.y / gfx::PIXEL_SCALE as f64 > gfx.height as f64
+                    {
+                        return;
+                    }
+                    let (new_col, new_row) = window_to_grid(
+                        position.x,
+                        position.y,
+                        gfx::PIXEL_SCALE,
+                        gfx::PIXELS_PER_CELL,
+                    );
+
+                    if new_row != row || new_col != col {
+                        keyboard = false;
+                        row = new_row;
+                        col = new_col;
 
+                        if let Screen::Playing = screen {
                             game.handle_mouse_move(row, col);
                         }
                     }
-                    _ => {}
                 }
+                _ => {}
             }
-            _ => {}
         }
-        //Mouse button press/release
     });
 
     // let mut n = 1;
@@ -140,6 +379,40 @@ fn main() {
     // println!("Solved {} puzzles", solved);
 }
 
+// What the background check has to say about the player's position, for the title
+fn status(game: &Game) -> &'static str {
+    if game.is_doomed() {
+        " (no longer solvable)"
+    } else if game.is_solving() {
+        " (solving...)"
+    } else if game.found_no_solution() {
+        " (no solution from here)"
+    } else if game.checking().is_some() {
+        " (checking...)"
+    } else {
+        ""
+    }
+}
+
+fn set_title(gfx: &gfx::Gfx, screen: &Screen, n: u32, game: &Game) {
+    let title = match screen {
+        Screen::Playing => format!(
+            "Flow Four - Level {}, {} colours{}",
+            n,
+            gfx.scheme.name(),
+            status(game)
+        ),
+        Screen::Complete { .. } => format!("Level {} complete! [N]ext  [R]eplay  [M]enu", n),
+        Screen::Failed { .. } => format!("Level {} failed! [N]ext  [R]eplay  [M]enu", n),
+        Screen::Menu => format!(
+            "Flow Four - Level {}, {} colours [Left/Right] choose  [Enter] play",
+            n,
+            gfx.scheme.name()
+        ),
+    };
+    gfx.window.set_title(&title);
+}
+
 fn window_to_grid(x: f64, y: f64, pixel_scale: u32, pixels_per_cell: u32) -> (usize, usize) {
     let cell_size = pixel_scale * pixels_per_cell;
     let grid_x = min((x as u32 / cell_size) as usize, COLS);
@@ -162,8 +435,8 @@ fn window_to_grid(x: f64, y: f64, pixel_scale: u32, pixels_per_cell: u32) -> (us
 
 // // Mouse movement
 // WindowEvent::CursorMoved { position, .. } => {
-//     if position.x as f64 / gfx::PIXEL_SCALE as f64 > gfx.width as f64
-//         || position.y as f64 / gfx::PIXEL_SCALE as f64 > gfx.height as f64
+//     if position.x / gfx::PIXEL_SCALE as f64 > gfx.width as f64
+//         || position.y / gfx::PIXEL_SCALE as f64 > gfx.height as f64
 //     {
 //         println!(
 //             "Position: {:?} Limits: {:?}",
@@ -0,0 +1,232 @@
+// Multi-threaded search. Each thread runs its own SolverStack on a subtree (a job). When a thread
+// runs out of work it waits on the shared pool, and busy threads notice and give away the untried
+// move nearest their root, which is the biggest subtree they have.
+//
+// Every job carries its path from the root (see SolverStack::path), so solutions can be compared
+// in the order a single-threaded search would reach them. When the search runs to the end, the
+// reported solution is always the one with the smallest path, whatever the timing, and subtrees
+// after it are abandoned. The step limit is shared, though, so how far each subtree got when it
+// runs out depends on the timing: a search that gives up may report a later solution, or none,
+// and may do so differently from one run to the next.
+//
+// solve_all is the other kind of parallelism: many boards at once, one per worker.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{Control, Limits, PuzzleSolver, SolveOutcome};
+use crate::board::Board;
+use crate::heuristic::Heuristic;
+use crate::solver::Solver;
+use crate::solver_stack::SolverStack;
+
+const CHECK_EVERY: u64 = 256; // Steps between looks at the shared state
+
+struct Job {
+    path: Vec<usize>,
+    solver: Solver,
+}
+
+struct Pool {
+    jobs: Vec<Job>,
+    idle: usize,
+}
+
+struct Shared {
+    pool: Mutex<Pool>,
+    wake: Condvar,
+    best: Mutex<Option<(Vec<usize>, Board)>>,
+    stop: AtomicBool,
+    steps: AtomicU64,
+    branches: AtomicU64,
+    lookups: AtomicU64,
+    hits: AtomicU64,
+}
+
+pub struct Report {
+    pub solution: Option<Board>,
+    pub steps: u64,
+    pub branches: u64,
+    pub lookups: u64, // Transposition table lookups, and how many found a known dead state
+    pub hits: u64,
+    pub gave_up: bool, // Hit the step limit or was cancelled before finishing
+    pub time: Duration,
+}
+
+pub fn solve(board: &Board, heuristic: Heuristic, threads: usize, limits: &Limits) -> Report {
+    let start = Instant::now();
+    let threads = threads.max(1);
+    let shared = Shared {
+        pool: Mutex::new(Pool {
+            // No jobs at all if the paths already drawn can't be finished
+            jobs: Solver::from_progress(board)
+                .map(|solver| Job {
+                    path: Vec::new(),
+                    solver: solver.with_heuristic(heuristic),
+                })
+                .into_iter()
+                .collect(),
+            idle: 0,
+        }),
+        wake: Condvar::new(),
+        best: Mutex::new(None),
+        stop: AtomicBool::new(false),
+        steps: AtomicU64::new(0),
+        branches: AtomicU64::new(0),
+        lookups: AtomicU64::new(0),
+        hits: AtomicU64::new(0),
+    };
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| worker(&shared, threads, limits.steps, &limits.control));
+        }
+    });
+
+    let steps = shared.steps.load(Ordering::Relaxed);
+    Report {
+        solution: shared.best.into_inner().unwrap().map(|(_, board)| board),
+        steps,
+        branches: shared.branches.load(Ordering::Relaxed),
+        lookups: shared.lookups.load(Ordering::Relaxed),
+        hits: shared.hits.load(Ordering::Relaxed),
+        gave_up: steps >= limits.steps || limits.control.is_cancelled(),
+        time: start.elapsed(),
+    }
+}
+
+///Solves every board, `workers` at a time with a solver each from `solver`, and hands each
+///outcome to `on_outcome` with the board's index as soon as all boards before it are done
+pub fn solve_all(
+    boards: &[Board],
+    workers: usize,
+    solver: impl Fn() -> Box<dyn PuzzleSolver> + Sync,
+    limits: &Limits,
+    mut on_outcome: impl FnMut(usize, SolveOutcome),
+) {
+    let next = AtomicUsize::new(0);
+    let (send, receive) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            let send = send.clone();
+            let (next, solver) = (&next, &solver);
+            scope.spawn(move || {
+                let mut solver = solver();
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(board) = boards.get(i) else { break };
+                    send.send((i, solver.solve(board, limits))).unwrap();
+                }
+            });
+        }
+        drop(send);
+
+        // Outcomes arrive in whatever order they finish; hold the early ones back
+        let mut waiting = BTreeMap::new();
+        let mut due = 0;
+        for (i, outcome) in receive {
+            waiting.insert(i, outcome);
+            while let Some(outcome) = waiting.remove(&due) {
+                on_outcome(due, outcome);
+                due += 1;
+            }
+        }
+    });
+}
+
+fn worker(shared: &Shared, threads: usize, step_limit: u64, control: &Control) {
+    while let Some(Job { path, solver }) = take(shared, threads) {
+        let mut stack = SolverStack::new(solver);
+        let mut steps = 0;
+        while !stack.done() && !stack.failed() {
+            stack.step();
+            steps += 1;
+            if steps % CHECK_EVERY != 0 {
+                continue;
+            }
+            let total = shared.steps.fetch_add(CHECK_EVERY, Ordering::Relaxed);
+            control.report(total + CHECK_EVERY);
+            if shared.stop.load(Ordering::Relaxed) || total >= step_limit || control.is_cancelled()
+            {
+                shared.stop.store(true, Ordering::Relaxed);
+                shared.wake.notify_all();
+                break;
+            }
+            if beaten(shared, &[path.as_slice(), &stack.path()].concat()) {
+                break;
+            }
+            share(shared, &path, &mut stack);
+        }
+        shared
+            .steps
+            .fetch_add(steps % CHECK_EVERY, Ordering::Relaxed);
+        shared.branches.fetch_add(stack.branches, Ordering::Relaxed);
+        shared.lookups.fetch_add(stack.lookups, Ordering::Relaxed);
+        shared.hits.fetch_add(stack.hits, Ordering::Relaxed);
+
+        if stack.done() {
+            let found = [path.as_slice(), &stack.path()].concat();
+            let mut best = shared.best.lock().unwrap();
+            if best
+                .as_ref()
+                .is_none_or(|(best_path, _)| found < *best_path)
+            {
+                *best = Some((found, stack.get_board().clone()));
+            }
+        }
+    }
+}
+
+// Waits for the next job, earliest in search order first. None once the search is over: every
+// thread is idle with nothing left in the pool, or it was stopped.
+fn take(shared: &Shared, threads: usize) -> Option<Job> {
+    let mut pool = shared.pool.lock().unwrap();
+    pool.idle += 1;
+    loop {
+        if shared.stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        pool.jobs.retain(|job| !beaten(shared, &job.path));
+        if let Some(first) =
+            (0..pool.jobs.len()).min_by(|a, b| pool.jobs[*a].path.cmp(&pool.jobs[*b].path))
+        {
+            pool.idle -= 1;
+            return Some(pool.jobs.swap_remove(first));
+        }
+        if pool.idle == threads {
+            shared.stop.store(true, Ordering::Relaxed);
+            shared.wake.notify_all();
+            return None;
+        }
+        pool = shared.wake.wait(pool).unwrap();
+    }
+}
+
+// Gives a subtree to the pool if some thread is waiting for work
+fn share(shared: &Shared, path: &[usize], stack: &mut SolverStack) {
+    let mut pool = shared.pool.lock().unwrap();
+    if pool.idle == 0 || !pool.jobs.is_empty() {
+        return;
+    }
+    if let Some((rest, solver)) = stack.split() {
+        pool.jobs.push(Job {
+            path: [path, &rest].concat(),
+            solver,
+        });
+        shared.wake.notify_one();
+    }
+}
+
+// True if a solution earlier in search order than `path` has already been found
+fn beaten(shared: &Shared, path: &[usize]) -> bool {
+    shared
+        .best
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|(best, _)| best.as_slice() < path)
+}
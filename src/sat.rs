@@ -0,0 +1,435 @@
+// A small CDCL SAT solver: unit propagation with two watched literals, conflict analysis down to the
+// first unique implication point, non-chronological backjumping, activity-based branching with
+// saved phases, and Luby restarts. Learnt clauses are never deleted; the puzzles are small enough.
+//
+// Clauses come in DIMACS style: variables numbered from 1, a negative number for a negated literal.
+
+use crate::backend::Control;
+
+// A literal is 2 * var + 1 if negated, with vars counted from 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Lit(u32);
+
+impl Lit {
+    fn from_dimacs(l: i32) -> Lit {
+        Lit((l.unsigned_abs() - 1) * 2 + (l < 0) as u32)
+    }
+
+    fn var(self) -> usize {
+        (self.0 / 2) as usize
+    }
+
+    fn negated(self) -> bool {
+        self.0 & 1 == 1
+    }
+
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+const UNSET: i8 = 0;
+const RESTART_UNIT: u64 = 100; // Conflicts per unit of the Luby sequence
+const DECAY: f64 = 0.95;
+const CHECK_EVERY: u64 = 64; // Conflicts between reports of progress and looks for cancellation
+
+pub enum SatResult {
+    Sat(Vec<bool>), // Value of every variable, from variable 1
+    Unsat,
+    GaveUp, // Hit the conflict limit or was cancelled
+}
+
+pub struct Sat {
+    clauses: Vec<Vec<Lit>>,   // The first two literals of each clause are watched
+    watches: Vec<Vec<usize>>, // Clauses watching each literal, to visit when it becomes false
+    values: Vec<i8>,          // Per var: 1 true, -1 false, UNSET
+    level: Vec<usize>,
+    reason: Vec<Option<usize>>, // Clause that forced each var, None for decisions
+    phase: Vec<bool>,           // Last value of each var, tried first when deciding on it again
+    activity: Vec<f64>,
+    bump: f64,
+    seen: Vec<bool>,
+    trail: Vec<Lit>,
+    levels: Vec<usize>, // Trail length at the start of each decision level
+    queue: usize,       // Trail entries not yet propagated start here
+    unsat: bool,        // Contradiction found while adding clauses
+    pub conflicts: u64,
+    pub decisions: u64,
+}
+
+impl Sat {
+    pub fn new(vars: usize, clauses: &[Vec<i32>]) -> Sat {
+        let mut sat = Sat {
+            clauses: Vec::new(),
+            watches: vec![Vec::new(); vars * 2],
+            values: vec![UNSET; vars],
+            level: vec![0; vars],
+            reason: vec![None; vars],
+            phase: vec![false; vars],
+            activity: vec![0.0; vars],
+            bump: 1.0,
+            seen: vec![false; vars],
+            trail: Vec::new(),
+            levels: Vec::new(),
+            queue: 0,
+            unsat: false,
+            conflicts: 0,
+            decisions: 0,
+        };
+        for clause in clauses {
+            sat.add_clause(clause);
+        }
+        sat
+    }
+
+    // Adds an input clause at level 0
+    fn add_clause(&mut self, clause: &[i32]) {
+        let mut lits: Vec<Lit> = clause.iter().map(|l| Lit::from_dimacs(*l)).collect();
+        lits.sort_by_key(|l| l.0);
+        lits.dedup();
+        // Always true, or already true at level 0
+        if lits.windows(2).any(|w| w[0].var() == w[1].var())
+            || lits.iter().any(|l| self.value(*l) == 1)
+        {
+            return;
+        }
+        lits.retain(|l| self.value(*l) == UNSET);
+        match lits.len() {
+            0 => self.unsat = true,
+            1 => {
+                self.assign(lits[0], None);
+                if self.propagate().is_some() {
+                    self.unsat = true;
+                }
+            }
+            _ => {
+                self.watch(lits);
+            }
+        }
+    }
+
+    fn watch(&mut self, lits: Vec<Lit>) -> usize {
+        let i = self.clauses.len();
+        self.watches[lits[0].0 as usize].push(i);
+        self.watches[lits[1].0 as usize].push(i);
+        self.clauses.push(lits);
+        i
+    }
+
+    fn value(&self, l: Lit) -> i8 {
+        let v = self.values[l.var()];
+        if l.negated() { -v } else { v }
+    }
+
+    fn assign(&mut self, l: Lit, reason: Option<usize>) {
+        let v = l.var();
+        self.values[v] = if l.negated() { -1 } else { 1 };
+        self.level[v] = self.levels.len();
+        self.reason[v] = reason;
+        self.trail.push(l);
+    }
+
+    // Assigns everything the trail implies. Returns a clause with every literal false, if any.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.queue < self.trail.len() {
+            let falsified = self.trail[self.queue].not();
+            self.queue += 1;
+            let mut watching = std::mem::take(&mut self.watches[falsified.0 as usize]);
+            let mut kept = 0;
+            let mut conflict = None;
+            for i in 0..watching.len() {
+                let ci = watching[i];
+                if conflict.is_some() {
+                    watching[kept] = ci;
+                    kept += 1;
+                    continue;
+                }
+                let clause = &mut self.clauses[ci];
+                if clause[0] == falsified {
+                    clause.swap(0, 1);
+                }
+                let first = clause[0];
+                // Satisfied already
+                if self.values[first.var()] != UNSET
+                    && (self.values[first.var()] == 1) != first.negated()
+                {
+                    watching[kept] = ci;
+                    kept += 1;
+                    continue;
+                }
+                // Look for another literal that isn't false to watch instead
+                let values = &self.values;
+                let replacement = (2..clause.len()).find(|k| {
+                    let l = clause[*k];
+                    values[l.var()] == UNSET || (values[l.var()] == 1) != l.negated()
+                });
+                if let Some(k) = replacement {
+                    clause.swap(1, k);
+                    let l = clause[1];
+                    self.watches[l.0 as usize].push(ci);
+                    continue;
+                }
+                watching[kept] = ci;
+                kept += 1;
+                if self.value(first) == UNSET {
+                    self.assign(first, Some(ci));
+                } else {
+                    conflict = Some(ci);
+                }
+            }
+            watching.truncate(kept);
+            self.watches[falsified.0 as usize] = watching;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    // Learns a clause from a conflict by resolving back to the first unique implication point.
+    // Returns it with the asserting literal first, along with the level to jump back to.
+    fn analyze(&mut self, mut conflict: usize) -> (Vec<Lit>, usize) {
+        let mut learnt = vec![Lit(0)];
+        let mut open = 0; // Literals of the current level still to resolve
+        let mut i = self.trail.len();
+        let level = self.levels.len();
+        let mut skip_first = false;
+        loop {
+            for k in skip_first as usize..self.clauses[conflict].len() {
+                let l = self.clauses[conflict][k];
+                let v = l.var();
+                if self.seen[v] || self.level[v] == 0 {
+                    continue;
+                }
+                self.seen[v] = true;
+                self.bump_activity(v);
+                if self.level[v] == level {
+                    open += 1;
+                } else {
+                    learnt.push(l);
+                }
+            }
+            // Latest marked literal on the trail
+            loop {
+                i -= 1;
+                if self.seen[self.trail[i].var()] {
+                    break;
+                }
+            }
+            let p = self.trail[i];
+            self.seen[p.var()] = false;
+            open -= 1;
+            if open == 0 {
+                learnt[0] = p.not();
+                break;
+            }
+            conflict = self.reason[p.var()].unwrap();
+            skip_first = true;
+        }
+        for l in &learnt[1..] {
+            self.seen[l.var()] = false;
+        }
+
+        // Watch the literal from the highest remaining level second, so it is the first unassigned
+        let mut back_to = 0;
+        for k in 1..learnt.len() {
+            if self.level[learnt[k].var()] > back_to {
+                back_to = self.level[learnt[k].var()];
+                learnt.swap(1, k);
+            }
+        }
+        (learnt, back_to)
+    }
+
+    fn bump_activity(&mut self, v: usize) {
+        self.activity[v] += self.bump;
+        if self.activity[v] > 1e100 {
+            self.activity.iter_mut().for_each(|a| *a *= 1e-100);
+            self.bump *= 1e-100;
+        }
+    }
+
+    fn backjump(&mut self, level: usize) {
+        if self.levels.len() <= level {
+            return;
+        }
+        for l in self.trail.drain(self.levels[level]..) {
+            self.phase[l.var()] = !l.negated();
+            self.values[l.var()] = UNSET;
+            self.reason[l.var()] = None;
+        }
+        self.levels.truncate(level);
+        self.queue = self.trail.len();
+    }
+
+    // Unassigned var with the highest activity
+    fn pick(&self) -> Option<usize> {
+        (0..self.values.len())
+            .filter(|v| self.values[*v] == UNSET)
+            .max_by(|a, b| self.activity[*a].total_cmp(&self.activity[*b]))
+    }
+
+    ///Runs until the clauses are satisfied or shown unsatisfiable, or `conflict_limit` conflicts,
+    ///or until cancelled
+    pub fn solve(&mut self, conflict_limit: u64, control: &Control) -> SatResult {
+        if self.unsat || self.propagate().is_some() {
+            return SatResult::Unsat;
+        }
+        let mut restart = 1;
+        let mut since_restart = 0;
+        loop {
+            if let Some(conflict) = self.propagate() {
+                self.conflicts += 1;
+                since_restart += 1;
+                if self.levels.is_empty() {
+                    return SatResult::Unsat;
+                }
+                if self.conflicts >= conflict_limit {
+                    return SatResult::GaveUp;
+                }
+                if self.conflicts.is_multiple_of(CHECK_EVERY) {
+                    control.report(self.conflicts);
+                    if control.is_cancelled() {
+                        return SatResult::GaveUp;
+                    }
+                }
+                let (learnt, back_to) = self.analyze(conflict);
+                self.backjump(back_to);
+                if learnt.len() == 1 {
+                    self.assign(learnt[0], None);
+                } else {
+                    let first = learnt[0];
+                    let ci = self.watch(learnt);
+                    self.assign(first, Some(ci));
+                }
+                self.bump /= DECAY;
+
+                if since_restart >= luby(restart) * RESTART_UNIT {
+                    self.backjump(0);
+                    restart += 1;
+                    since_restart = 0;
+                }
+            } else {
+                let Some(v) = self.pick() else {
+                    return SatResult::Sat(self.values.iter().map(|v| *v == 1).collect());
+                };
+                self.decisions += 1;
+                self.levels.push(self.trail.len());
+                let l = Lit(v as u32 * 2 + !self.phase[v] as u32);
+                self.assign(l, None);
+            }
+        }
+    }
+}
+
+// The i-th term (from 1) of the Luby sequence 1 1 2 1 1 2 4 1 1 2 ...
+fn luby(i: u64) -> u64 {
+    let mut k = 1;
+    while (1 << k) - 1 < i {
+        k += 1;
+    }
+    if (1 << k) - 1 == i {
+        1 << (k - 1)
+    } else {
+        luby(i - (1 << (k - 1)) + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(vars: usize, clauses: &[Vec<i32>]) -> SatResult {
+        Sat::new(vars, clauses).solve(u64::MAX, &Control::default())
+    }
+
+    fn satisfies(model: &[bool], clauses: &[Vec<i32>]) -> bool {
+        clauses.iter().all(|c| {
+            c.iter()
+                .any(|l| model[l.unsigned_abs() as usize - 1] == (*l > 0))
+        })
+    }
+
+    // Every one of `pigeons` pigeons in one of `holes` holes, and no two in the same hole.
+    // Variable h * pigeons + p + 1 puts pigeon p in hole h.
+    fn pigeonhole(pigeons: usize, holes: usize) -> (usize, Vec<Vec<i32>>) {
+        let var = |p: usize, h: usize| (h * pigeons + p + 1) as i32;
+        let mut clauses: Vec<Vec<i32>> = (0..pigeons)
+            .map(|p| (0..holes).map(|h| var(p, h)).collect())
+            .collect();
+        for h in 0..holes {
+            for p in 0..pigeons {
+                for q in p + 1..pigeons {
+                    clauses.push(vec![-var(p, h), -var(q, h)]);
+                }
+            }
+        }
+        (pigeons * holes, clauses)
+    }
+
+    #[test]
+    fn satisfiable_formula() {
+        let clauses = vec![
+            vec![1, 2],
+            vec![-1, 3],
+            vec![-2, -3],
+            vec![-3, 4],
+            vec![2, -4],
+        ];
+        let SatResult::Sat(model) = solve(4, &clauses) else {
+            panic!("Satisfiable formula not solved");
+        };
+        assert!(satisfies(&model, &clauses));
+    }
+
+    #[test]
+    fn pigeons_that_fit() {
+        let (vars, clauses) = pigeonhole(4, 4);
+        let SatResult::Sat(model) = solve(vars, &clauses) else {
+            panic!("Four pigeons fit in four holes");
+        };
+        assert!(satisfies(&model, &clauses));
+    }
+
+    #[test]
+    fn pigeons_that_dont_fit() {
+        let (vars, clauses) = pigeonhole(4, 3);
+        assert!(matches!(solve(vars, &clauses), SatResult::Unsat));
+    }
+
+    #[test]
+    fn unit_clauses_are_kept() {
+        let clauses = vec![vec![1], vec![-2], vec![-1, 2, 3]];
+        let SatResult::Sat(model) = solve(3, &clauses) else {
+            panic!("Satisfiable formula not solved");
+        };
+        assert_eq!(model, vec![true, false, true]);
+    }
+
+    #[test]
+    fn contradicting_unit_clauses() {
+        assert!(matches!(solve(1, &[vec![1], vec![-1]]), SatResult::Unsat));
+    }
+
+    #[test]
+    fn empty_clause() {
+        assert!(matches!(solve(2, &[vec![1, 2], vec![]]), SatResult::Unsat));
+    }
+
+    #[test]
+    fn gives_up_at_the_conflict_limit() {
+        let (vars, clauses) = pigeonhole(6, 5);
+        let mut sat = Sat::new(vars, &clauses);
+        assert!(matches!(
+            sat.solve(1, &Control::default()),
+            SatResult::GaveUp
+        ));
+        assert_eq!(sat.conflicts, 1);
+    }
+
+    #[test]
+    fn luby_sequence() {
+        let terms: Vec<u64> = (1..=15).map(luby).collect();
+        assert_eq!(terms, [1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8]);
+    }
+}
@@ -1,4 +1,8 @@
-use crate::board::Board;
+use crate::bitboard::{AnyBitboard, Bitboard, Bits, Words};
+use crate::board::{Board, Cell};
+use crate::heuristic::Heuristic;
+use crate::zobrist::Zobrist;
+use std::sync::Arc;
 
 fn adjacent(a: Coord, b: Coord) -> bool {
     let (x1, y1) = (a.0 as isize, a.1 as isize);
@@ -26,18 +30,64 @@ impl Flow {
         [*self.pair[0].last().unwrap(), *self.pair[1].last().unwrap()]
     }
 }
-type Coord = (usize, usize); // row, col
+pub type Coord = (usize, usize); // row, col
 
-// type Move = (Coord, &mut Flow, usize);
+const MAX_CUT: usize = 3; // Widest corridor checked for bottlenecks
+
+type LineCell = fn(usize, usize) -> Coord; // (line, i) to the i-th cell of a row or column
+
+pub type Move = (Coord, usize, usize); // (where_to_move, which_flow, which_end_of_pair)
+
+// One entry per move made, so that moves can be taken back in reverse order
+#[derive(Clone, Copy, Debug)]
+struct Trail {
+    m: Move,
+    completed: bool, // This move joined the flow's two ends
+    guessed: bool,   // Made at a branch rather than forced
+}
 
 #[derive(Clone, Debug)]
 pub struct Solver {
     flows: Vec<Flow>,
     pub board: Board,
+    bits: AnyBitboard, // Kept in step with board for the checks that work on whole regions
+    trail: Vec<Trail>,
+    heuristic: Heuristic,
+    zobrist: Arc<Zobrist>, // Shared by every clone of the solver
+    hash: u64,
 }
 
 impl Solver {
+    ///Solver for a board with no path cells on it, or with paths that from_progress accepts
     pub fn new(b: &Board) -> Solver {
+        Self::from_progress(b).expect("Inconsistent paths on the board")
+    }
+
+    ///Solver that carries on from the paths already on a board. Each flow's ends are followed
+    ///from its heads along path cells of its colour. None if the paths can't be part of a
+    ///solution: a path forks, touches itself, or has cells not connected to a head.
+    pub fn from_progress(b: &Board) -> Option<Solver> {
+        let flows = Self::trace(b)?;
+        let colors = b.iter().filter(|c| !c.is_empty()).map(Cell::color).max();
+        let zobrist = Zobrist::new(b.rows, b.cols, colors.map_or(0, |c| c as usize + 1));
+        let tips: Vec<Coord> = flows.iter().flat_map(|f| f.tips()).collect();
+        Some(Self {
+            hash: zobrist.hash(b, &tips),
+            zobrist: Arc::new(zobrist),
+            flows,
+            board: b.clone(),
+            bits: AnyBitboard::from_board(b),
+            trail: Vec::new(),
+            heuristic: Heuristic::default(),
+        })
+    }
+
+    ///True if from_progress would accept the paths on the board
+    pub fn consistent(b: &Board) -> bool {
+        Self::trace(b).is_some()
+    }
+
+    fn trace(b: &Board) -> Option<Vec<Flow>> {
         let mut flows: Vec<Flow> = vec![];
 
         //Need to go from a board to a sparse board of heads.
@@ -46,7 +96,7 @@ impl Solver {
         // If not, create a new pair.
         for i in 0..b.len() {
             if b[i].is_head() {
-                let pos: Coord = Board::inverse_ind(i);
+                let pos: Coord = b.inverse_ind(i);
                 if let Some(target) = flows.iter_mut().find(|flow| flow.color == b[i].color()) {
                     target.pair[1] = vec![pos];
                 } else {
@@ -59,69 +109,83 @@ impl Solver {
             }
         }
 
-        Self {
-            flows,
-            board: b.clone(),
+        if !b.is_legal() {
+            return None;
+        }
+        let mut used: Vec<bool> = b.iter().map(Cell::is_head).collect();
+        for f in &mut flows {
+            let sides = if f.pair[0] == f.pair[1] { 1 } else { 2 };
+            for side in 0..sides {
+                loop {
+                    let tip = *f.pair[side].last().unwrap();
+                    let path = Cell::Path { color: f.color };
+                    let mut next = b
+                        .neighbors(tip.0, tip.1)
+                        .filter(|n| b[*n] == path && !used[n.0 * b.cols + n.1]);
+                    let Some(n) = next.next() else {
+                        break;
+                    };
+                    if next.next().is_some() {
+                        return None;
+                    }
+                    used[n.0 * b.cols + n.1] = true;
+                    f.pair[side].push(n);
+                }
+            }
+            Self::check_complete(f);
+        }
+        if (0..b.len()).any(|i| !b[i].is_empty() && !used[i]) {
+            return None;
         }
+        Some(flows)
     }
 
-    fn forced_move(&self) -> Option<(Coord, usize, usize)> {
-        // (where_to_move, which_flow, first_flow_in_pair?)
-        let board = &self.board;
+    pub fn with_heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    fn forced_move(&self) -> Option<Move> {
         for (i, f) in self.flows.iter().enumerate() {
             for lefty in 0..=1 {
-                let sf = f.pair[lefty].clone();
-                let moves: Vec<Coord> = Self::moves_from(board, *sf.last().unwrap());
+                let moves: Vec<Coord> = self.moves_for(f, lefty);
                 if moves.len() == 1 && !f.complete {
                     return Some((moves[0], i, lefty));
                 }
             }
         }
-        return None;
+        None
     }
 
-    pub fn binary_step(&mut self) -> Option<(Self, Self)> {
-        let board = &self.board;
-        let mut other = self.clone();
-
-        let mut fork_info: Option<(Coord, usize, usize, Coord)> = None;
-
-        for (flow_idx, (f_self, f_other)) in self
-            .flows
-            .iter_mut()
-            .zip(other.flows.iter_mut())
-            .enumerate()
-        {
+    ///Every way forward from the tip with a choice to make that the heuristic likes best, in the
+    ///order they should be tried
+    pub fn branch_step(&self) -> Option<Vec<Move>> {
+        let mut best: Option<((usize, usize), Vec<Move>)> = None;
+        for (flow_idx, f) in self.flows.iter().enumerate() {
+            if f.complete {
+                continue;
+            }
+            let tips = f.tips();
             for i in 0..=1 {
-                let sf = f_self.pair[i].clone();
-                let moves = Self::moves_from(board, *sf.last().unwrap());
-                if moves.len() == 2 && !f_self.complete {
-                    fork_info = Some((moves[0], flow_idx, i, moves[1]));
-                    break;
+                let mut moves = self.moves_for(f, i);
+                if moves.len() < 2 {
+                    continue;
+                }
+                let other_moves = self.moves_for(f, 1 - i).len();
+                let rank = self.heuristic.score(&self.board, tips, &moves, other_moves);
+                if best.as_ref().is_none_or(|(best_rank, _)| rank < *best_rank) {
+                    self.heuristic.order(&self.board, &mut moves);
+                    best = Some((rank, moves.into_iter().map(|m| (m, flow_idx, i)).collect()));
                 }
-            }
-            if fork_info.is_some() {
-                break;
             }
         }
-
-        if let Some((m0, flow_idx, i, m1)) = fork_info {
-            self.make((m0, flow_idx, i));
-            other.make((m1, flow_idx, i));
-            Some((self.clone(), other))
-        } else {
-            // self.board.print();
-            // println!("Any moves left? : {}", self.any_moves_left());
-            // panic!()
-            return None;
-        }
+        best.map(|(_, moves)| moves)
     }
 
     fn any_moves_left(&self) -> bool {
         for f in &self.flows {
-            let tips = f.tips();
             for i in 0..=1 {
-                if Self::moves_from(&self.board, tips[i]).len() > 0 && !f.complete {
+                if !self.moves_for(f, i).is_empty() && !f.complete {
                     return true;
                 }
             }
@@ -131,9 +195,8 @@ impl Solver {
 
     fn some_blocked_tip(&self) -> bool {
         for f in &self.flows {
-            let tips = f.tips();
             for i in 0..=1 {
-                if Self::moves_from(&self.board, tips[i]).len() == 0 && !f.complete {
+                if !f.complete && self.moves_for(f, i).is_empty() {
                     return true;
                 }
             }
@@ -141,39 +204,186 @@ impl Solver {
         false
     }
 
-    fn some_pocket(&self) -> bool {
-        'L: for i in 0..self.board.rows * self.board.cols {
-            if self.board[i].is_empty() {
-                let (r, c) = Board::inverse_ind(i);
-                if self.board.empty_neighbors(r, c).iter().flatten().count() == 0 {
-                    for f in &self.flows {
-                        for i in 0..=1 {
-                            if adjacent((r, c), *f.pair[i].last().unwrap()) {
-                                continue 'L;
-                            }
-                        }
+    // Every empty cell ends up as a path cell with two neighbours of its colour, each of which is
+    // an empty cell now or the tip of an unfinished flow. Fewer than two of those makes it a dead
+    // end; none at all is a pocket.
+    fn some_dead_end(&self) -> bool {
+        match &self.bits {
+            AnyBitboard::Inline(bits) => self.some_dead_end_in(bits),
+            AnyBitboard::Heap(bits) => self.some_dead_end_in(bits),
+        }
+    }
+
+    fn some_dead_end_in<W: Words>(&self, bits: &Bitboard<W>) -> bool {
+        let open_tips: Vec<Coord> = self
+            .flows
+            .iter()
+            .filter(|f| !f.complete)
+            .flat_map(|f| f.tips())
+            .collect();
+        let empty = bits.empty();
+        let mut open = bits.cells(&open_tips);
+        open |= &empty;
+        !bits.lonely(&empty, &open).is_empty()
+    }
+
+    // Every unfinished flow needs both tips on one region of empty cells, and every region needs a
+    // flow with both tips on it to fill it
+    fn disconnected(&self) -> bool {
+        match &self.bits {
+            AnyBitboard::Inline(bits) => self.disconnected_in(bits),
+            AnyBitboard::Heap(bits) => self.disconnected_in(bits),
+        }
+    }
+
+    fn disconnected_in<W: Words>(&self, bits: &Bitboard<W>) -> bool {
+        let regions = bits.regions(&bits.empty());
+        let mut usable = vec![false; regions.len()];
+        for f in self.flows.iter().filter(|f| !f.complete) {
+            let shared = self.shared_regions(bits, &regions, f);
+            if shared.is_empty() {
+                return true;
+            }
+            for region in shared {
+                usable[region] = true;
+            }
+        }
+        usable.contains(&false)
+    }
+
+    ///A straight run of up to MAX_CUT empty cells, closed off at both ends, can carry at most one
+    ///flow per cell. True if more flows than that are cut off from their partner without it.
+    ///Too slow to run after every move, so the search only checks it before branching.
+    pub fn bottleneck(&self) -> bool {
+        match &self.bits {
+            AnyBitboard::Inline(bits) => self.bottleneck_in(bits),
+            AnyBitboard::Heap(bits) => self.bottleneck_in(bits),
+        }
+    }
+
+    fn bottleneck_in<W: Words>(&self, bits: &Bitboard<W>) -> bool {
+        let empty = bits.empty();
+        let base = bits.regions(&empty);
+        let open: Vec<&Flow> = self.flows.iter().filter(|f| !f.complete).collect();
+        let sharing: Vec<Vec<usize>> = open
+            .iter()
+            .map(|f| self.shared_regions(bits, &base, f))
+            .collect();
+        // Reached straight after a backtrack the state may not have been through failed() yet
+        if sharing.iter().any(Vec::is_empty) {
+            return true;
+        }
+
+        // A new bottleneck is almost always made by the latest move, so only cuts next to it are
+        // checked. Skipping a cut can only mean less pruning.
+        let Some(last) = self.trail.last().map(|t| t.m.0) else {
+            return false;
+        };
+        let near = |c: &Coord| c.0.abs_diff(last.0) <= 1 && c.1.abs_diff(last.1) <= 1;
+
+        for cut in self
+            .straight_cuts()
+            .into_iter()
+            .filter(|cut| cut.iter().any(near))
+        {
+            // Only flows joined through the cut's region can be separated by it
+            let first = bits.bit(cut[0]);
+            let region = base.iter().position(|r| r.get(first)).unwrap();
+            let candidates: Vec<&Flow> = open
+                .iter()
+                .zip(&sharing)
+                .filter(|(_, shared)| shared.contains(&region))
+                .map(|(f, _)| *f)
+                .collect();
+            if candidates.len() <= cut.len() {
+                continue;
+            }
+
+            let mut rest = empty.clone();
+            rest.remove(&bits.cells(&cut));
+            let regions = bits.regions(&rest);
+            let crossing = candidates
+                .iter()
+                .filter(|f| self.shared_regions(bits, &regions, f).is_empty())
+                .count();
+            if crossing > cut.len() {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Maximal runs of empty cells along a row or column that are at most MAX_CUT long and have
+    // empty cells on both sides, so that blocking them could split a region in two
+    fn straight_cuts(&self) -> Vec<Vec<Coord>> {
+        let (rows, cols) = (self.board.rows, self.board.cols);
+        let mut cuts = Vec::new();
+        // (number of lines, line length, cell i of a line, step across the line)
+        let directions: [(usize, usize, LineCell, Coord); 2] = [
+            (rows, cols, |line, i| (line, i), (1, 0)),
+            (cols, rows, |line, i| (i, line), (0, 1)),
+        ];
+        for (lines, len, at, (dr, dc)) in directions {
+            // Runs on the outermost lines have a wall on one side
+            for line in 1..lines.saturating_sub(1) {
+                let mut start = 0;
+                for i in 0..=len {
+                    if i < len && self.board[at(line, i)].is_empty() {
+                        continue;
+                    }
+                    let run: Vec<Coord> = (start..i).map(|j| at(line, j)).collect();
+                    start = i + 1;
+                    if run.is_empty() || run.len() > MAX_CUT {
+                        continue;
+                    }
+                    let before = run
+                        .iter()
+                        .any(|(r, c)| self.board[(r - dr, c - dc)].is_empty());
+                    let after = run
+                        .iter()
+                        .any(|(r, c)| self.board[(r + dr, c + dc)].is_empty());
+                    if before && after {
+                        cuts.push(run);
                     }
-                    return true;
                 }
             }
         }
-        // let tips = f.tips();
-        // for i in 0..=1 {
-        //     if Self::moves_from(&self.board, tips[i]).len() == 0 && !f.complete {
-        //         return true;
-        //     }
-        // }
+        cuts
+    }
 
-        false
+    // Indices of the regions (from Bitboard::regions) bordering both tips of a flow
+    fn shared_regions<W: Words>(
+        &self,
+        bits: &Bitboard<W>,
+        regions: &[Bits<W>],
+        f: &Flow,
+    ) -> Vec<usize> {
+        let [a, b] = f.tips().map(|t| bits.neighbors(&bits.cells(&[t])));
+        (0..regions.len())
+            .filter(|i| regions[*i].intersects(&a) && regions[*i].intersects(&b))
+            .collect()
+    }
+
+    // Moves from one end of a flow that don't touch the flow anywhere but the tip being extended and
+    // the partner tip it may join. Any other contact is a U-turn or a 2x2 block of one colour,
+    // which no solution contains.
+    fn moves_for(&self, f: &Flow, side: usize) -> Vec<Coord> {
+        let tips = f.tips();
+        let mut moves = Self::moves_from(&self.board, tips[side]);
+        moves.retain(|m| {
+            self.board.neighbors(m.0, m.1).all(|n| {
+                n == tips[0]
+                    || n == tips[1]
+                    || self.board[n].is_empty()
+                    || self.board[n].color() != f.color
+            })
+        });
+        moves
     }
 
     pub fn moves_from(board: &Board, c: Coord) -> Vec<Coord> {
         let slots = board.empty_neighbors(c.0, c.1);
-        slots
-            .iter()
-            .filter(|result| result.is_some())
-            .map(|x| x.unwrap())
-            .collect()
+        slots.iter().flatten().copied().collect()
     }
 
     // pub fn solve_board(mut self) -> Board {
@@ -188,30 +398,67 @@ impl Solver {
         if self.board.is_solved() {
             return true;
         }
-        let move_result: Option<(Coord, usize, usize)> = self.forced_move();
-        if move_result.is_none() && !self.board.is_full() {
-            return false;
+        match self.forced_move() {
+            Some(m) => {
+                self.make(m, false);
+                true
+            }
+            None => false,
         }
-        let (loc, flow_idx, i) = move_result.unwrap();
-        let flow = &mut self.flows[flow_idx];
-
-        let c = flow.color;
-        flow.pair[i].push(loc);
-        Self::check_complete(flow);
-        self.board.add_path(loc.0, loc.1, c);
-        return true;
     }
 
-    fn make(&mut self, m: (Coord, usize, usize)) {
+    pub fn make(&mut self, m: Move, guessed: bool) {
         let (loc, flow_idx, i) = m;
         let flow = &mut self.flows[flow_idx];
         if flow.complete {
             return;
         }
         let c = flow.color;
+        self.hash ^= self.zobrist.tip(*flow.pair[i].last().unwrap())
+            ^ self.zobrist.tip(loc)
+            ^ self.zobrist.cell(loc, c);
         flow.pair[i].push(loc);
         Self::check_complete(flow);
+        let completed = flow.complete;
         self.board.add_path(loc.0, loc.1, c);
+        self.bits.set(loc, c);
+        self.trail.push(Trail {
+            m,
+            completed,
+            guessed,
+        });
+    }
+
+    ///Takes back moves until only the first `len` remain
+    pub fn undo_to(&mut self, len: usize) {
+        while self.trail.len() > len {
+            let Trail { m, completed, .. } = self.trail.pop().unwrap();
+            let (loc, flow_idx, i) = m;
+            let flow = &mut self.flows[flow_idx];
+            flow.pair[i].pop();
+            self.hash ^= self.zobrist.tip(*flow.pair[i].last().unwrap())
+                ^ self.zobrist.tip(loc)
+                ^ self.zobrist.cell(loc, flow.color);
+            if completed {
+                flow.complete = false;
+            }
+            self.board[loc] = Cell::Empty;
+            self.bits.clear(loc);
+        }
+        debug_assert!(self.bits.to_board() == self.board);
+        debug_assert!({
+            let tips: Vec<Coord> = self.flows.iter().flat_map(|f| f.tips()).collect();
+            self.hash == self.zobrist.hash(&self.board, &tips)
+        });
+    }
+
+    ///Zobrist hash of the board and the flows' tips
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn trail_len(&self) -> usize {
+        self.trail.len()
     }
 
     fn check_complete(flow: &mut Flow) {
@@ -230,12 +477,46 @@ impl Solver {
             return false;
         }
         !self.board.is_legal()
-            || self.board.is_full()
+            || self.bits.empty_count() == 0
+            || self.done()
             || self.some_blocked_tip()
-            || self.some_pocket()
+            || self.some_dead_end()
+            || self.disconnected()
     }
 
     pub fn get_board(&self) -> &Board {
         &self.board
     }
+
+    pub fn empty_cells(&self) -> usize {
+        self.bits.empty_count()
+    }
+
+    ///Number of connected regions of empty cells
+    pub fn empty_regions(&self) -> usize {
+        self.bits.region_count()
+    }
+
+    ///Number of moves made by guessing at a branch
+    pub fn guesses(&self) -> usize {
+        self.trail.iter().filter(|t| t.guessed).count()
+    }
+
+    ///How many moves each tip of each unfinished flow has
+    pub fn tip_moves(&self) -> Vec<usize> {
+        self.flows
+            .iter()
+            .filter(|f| !f.complete)
+            .flat_map(|f| [0, 1].map(|side| self.moves_for(f, side).len()))
+            .collect()
+    }
+
+    ///Cells filled by a guess at a branch rather than a forced move
+    pub fn branch_cells(&self) -> Vec<Coord> {
+        self.trail
+            .iter()
+            .filter(|t| t.guessed)
+            .map(|t| t.m.0)
+            .collect()
+    }
 }
@@ -8,12 +8,34 @@
 // because it would then be adjacent. Could take advantage of this.
 //
 
-use crate::solver::Solver;
+use crate::solver::{Coord, Move, Solver};
+use crate::zobrist::Table;
+
+// A branch point: how long the trail was when the guess was made, the state's hash, every move
+// from there in the order they are tried, the one being searched, and which one is next. The two
+// differ by more than one once split has given moves in between to another thread.
+struct Choice {
+    trail_len: usize,
+    hash: u64,
+    moves: Vec<Move>,
+    taken: usize,
+    next: usize,
+    shared: bool, // Part of its subtree was given to another thread (see split)
+}
+
+impl Choice {
+    fn exhausted(&self) -> bool {
+        self.next == self.moves.len()
+    }
+}
 
 pub struct SolverStack {
     current: Solver,
-    backlog: Vec<Solver>,
+    backlog: Vec<Choice>,
     failed: bool,
+    pub branches: u64, // Number of guesses made, i.e. search nodes
+    pub lookups: u64,  // Branch points looked up in the table of states with no solution
+    pub hits: u64,     // Lookups that found one, each saving a search of its subtree
 }
 
 impl SolverStack {
@@ -22,35 +44,107 @@ impl SolverStack {
             current: solver,
             backlog: Vec::new(),
             failed: false,
+            branches: 0,
+            lookups: 0,
+            hits: 0,
         }
     }
 
     pub fn step(&mut self) {
-        if self.current.failed() {
-            self.current = self.backlog.pop().unwrap();
-            self.failed = false;
+        if self.current.failed() && !self.backtrack() {
+            self.failed = true;
+            return;
         }
         if self.current.timid_step() {
             return;
         }
-        if let Some((branch1, branch2)) = self.current.binary_step() {
-            self.current = branch1;
-            self.backlog.push(branch2);
-            self.failed = false;
-        } else {
-            self.failed = true;
+        if self.known_dead() || self.current.bottleneck() {
+            self.failed = !self.backtrack();
+            return;
         }
+        if let Some(moves) = self.current.branch_step() {
+            let first = moves[0];
+            self.backlog.push(Choice {
+                trail_len: self.current.trail_len(),
+                hash: self.current.hash(),
+                moves,
+                taken: 0,
+                next: 1,
+                shared: false,
+            });
+            self.current.make(first, true);
+            self.branches += 1;
+        }
+    }
+
+    // True if an earlier search, here or in another thread, has already searched this state to
+    // the end without finding a solution
+    fn known_dead(&mut self) -> bool {
+        self.lookups += 1;
+        let hit = Table::shared().contains(self.current.hash());
+        self.hits += hit as u64;
+        hit
+    }
+
+    // Returns to the most recent branch point and takes its next move; false once none are left
+    fn backtrack(&mut self) -> bool {
+        while let Some(choice) = self.backlog.last_mut() {
+            if choice.exhausted() {
+                // Every move from here has been tried and failed
+                if !choice.shared {
+                    Table::shared().insert(choice.hash);
+                }
+                self.backlog.pop();
+                continue;
+            }
+            self.current.undo_to(choice.trail_len);
+            self.current.make(choice.moves[choice.next], true);
+            choice.taken = choice.next;
+            choice.next += 1;
+            self.branches += 1;
+            return true;
+        }
+        false
+    }
+
+    ///Which move was taken at each branch point on the way to the current state. Comparing paths
+    ///orders states the way the depth-first search visits them.
+    pub fn path(&self) -> Vec<usize> {
+        self.backlog.iter().map(|c| c.taken).collect()
+    }
+
+    ///Hands over the untried move nearest the root, as its path and the solver state it leads to,
+    ///so that another thread can search that subtree
+    pub fn split(&mut self) -> Option<(Vec<usize>, Solver)> {
+        let depth = self.backlog.iter().position(|c| !c.exhausted())?;
+        let mut path: Vec<usize> = self.backlog[..depth].iter().map(|c| c.taken).collect();
+        // This choice and the ones above it no longer see their whole subtree searched here
+        for choice in &mut self.backlog[..=depth] {
+            choice.shared = true;
+        }
+        let choice = &mut self.backlog[depth];
+        path.push(choice.next);
+
+        let mut solver = self.current.clone();
+        solver.undo_to(choice.trail_len);
+        solver.make(choice.moves[choice.next], true);
+        choice.next += 1;
+        Some((path, solver))
     }
 
     pub(crate) fn get_board(&self) -> &crate::board::Board {
         self.current.get_board()
     }
 
+    pub(crate) fn branch_cells(&self) -> Vec<Coord> {
+        self.current.branch_cells()
+    }
+
     pub(crate) fn done(&self) -> bool {
-        self.current.done()
+        self.current.get_board().is_solved()
     }
 
     pub(crate) fn failed(&self) -> bool {
-        self.failed || (self.current.failed() && self.backlog.is_empty())
+        self.failed || (self.current.failed() && self.backlog.iter().all(Choice::exhausted))
     }
 }
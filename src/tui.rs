@@ -0,0 +1,131 @@
+// Terminal player for sessions without a window, e.g. over SSH. The board is drawn with Board::render
+// and redrawn in place; a keyboard cursor stands in for the mouse and drives the same Game handlers,
+// so the rules are identical to the window game.
+//
+// Arrow keys move, Space puts the pen down (press) or lifts it (release), Backspace retracts, R resets,
+// N/P change level, C cycles the colour scheme and Q or Esc quits.
+
+use std::io::{self, Write};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    execute, queue,
+    terminal::{self, ClearType},
+};
+
+use crate::colors::Scheme;
+use crate::game::Game;
+use crate::{NUM_PUZZLES, initialize};
+
+pub fn run(n: u32) -> io::Result<()> {
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(
+        out,
+        terminal::EnterAlternateScreen,
+        terminal::Clear(ClearType::All),
+        cursor::Hide
+    )?;
+
+    let result = play(&mut out, n);
+
+    execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn play(out: &mut impl Write, mut n: u32) -> io::Result<()> {
+    let mut game = initialize(n);
+    let (mut row, mut col) = (0, 0);
+    let mut pen_down = false;
+    let mut scheme = Scheme::default();
+
+    loop {
+        draw(out, &mut game, n, (row, col), pen_down, scheme)?;
+
+        let Event::Key(KeyEvent {
+            code,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+
+        let (rows, cols) = (game.get_board().rows, game.get_board().cols);
+        let (old_row, old_col) = (row, col);
+        match code {
+            KeyCode::Up => row = row.saturating_sub(1),
+            KeyCode::Down => row = (row + 1).min(rows - 1),
+            KeyCode::Left => col = col.saturating_sub(1),
+            KeyCode::Right => col = (col + 1).min(cols - 1),
+            KeyCode::Char(' ') => {
+                if pen_down {
+                    game.handle_mouse_release();
+                    pen_down = false;
+                } else {
+                    game.handle_mouse_press(row, col);
+                    pen_down = true;
+                }
+            }
+            KeyCode::Backspace => {
+                if let Some(tip) = game.retract() {
+                    (row, col) = tip;
+                }
+            }
+            KeyCode::Char('r') => {
+                game.handle_right_click();
+                pen_down = false;
+            }
+            KeyCode::Char('n') | KeyCode::Char('p') => {
+                n = if code == KeyCode::Char('n') {
+                    n % NUM_PUZZLES + 1
+                } else {
+                    (n + NUM_PUZZLES - 2) % NUM_PUZZLES + 1
+                };
+                game = initialize(n);
+                pen_down = false;
+            }
+            KeyCode::Char('c') => scheme = scheme.next(),
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            _ => {}
+        }
+
+        if pen_down && (row, col) != (old_row, old_col) {
+            game.handle_mouse_move(row, col);
+        }
+    }
+}
+
+fn draw(
+    out: &mut impl Write,
+    game: &mut Game,
+    n: u32,
+    cursor: (usize, usize),
+    pen_down: bool,
+    scheme: Scheme,
+) -> io::Result<()> {
+    let status = if game.is_finished() {
+        format!("Level {} complete! [N]ext [P]rev [Q]uit", n)
+    } else {
+        format!(
+            "Level {} - pen {} - arrows move, Space pen, [R]eset, [N]ext, [P]rev, [C]olours ({}), [Q]uit",
+            n,
+            if pen_down { "down" } else { "up" },
+            scheme.name()
+        )
+    };
+
+    queue!(out, cursor::MoveTo(0, 0))?;
+    write!(
+        out,
+        "{}",
+        game.get_board()
+            .render(Some(cursor), scheme)
+            .replace('\n', "\r\n")
+    )?;
+    write!(out, "\r\n{}", status)?;
+    queue!(out, terminal::Clear(ClearType::UntilNewLine))?;
+    out.flush()
+}
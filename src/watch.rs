@@ -0,0 +1,128 @@
+// Watch mode: runs SolverStack a few steps per frame and draws the board it is currently exploring.
+// Cells the solver guessed at (branch_step) are washed out so they stand apart from forced moves.
+//
+// Space pauses, Right single-steps while paused, Up/Down double or halve the steps per frame,
+// C cycles the colour scheme, G toggles head glyphs, R restarts the level and N moves on to the
+// next one.
+
+use std::time::{Duration, Instant};
+
+use winit::{
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event_loop::ControlFlow,
+};
+
+use crate::board::Board;
+use crate::gfx::{Gfx, Palette};
+use crate::solver::Solver;
+use crate::solver_stack::SolverStack;
+use crate::{COLS, NUM_PUZZLES, ROWS, load_puzzle};
+
+const TRANSITION: Duration = Duration::from_millis(750);
+
+fn new_stack(n: u32) -> SolverStack {
+    let mut board = Board::load_board(&load_puzzle(n), ROWS, COLS);
+    board.strip();
+    SolverStack::new(Solver::new(&board))
+}
+
+pub fn run(mut n: u32, mut steps_per_frame: u32) {
+    let mut stack = new_stack(n);
+    let mut steps: u64 = 0;
+    let mut paused = false;
+    let mut single_step = false;
+    let mut finished: Option<Instant> = None;
+
+    let (mut gfx, event_loop) = Gfx::new::<()>(ROWS as u32, COLS as u32);
+    set_title(&gfx, n, steps_per_frame, paused);
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+
+        if let Event::MainEventsCleared = event {
+            if finished.is_none() {
+                let budget = if paused {
+                    single_step as u32
+                } else {
+                    steps_per_frame
+                };
+                single_step = false;
+                for _ in 0..budget {
+                    if stack.done() || stack.failed() {
+                        break;
+                    }
+                    stack.step();
+                    steps += 1;
+                }
+                if stack.done() || stack.failed() {
+                    let outcome = if stack.get_board().is_solved() {
+                        "solved"
+                    } else {
+                        "failed"
+                    };
+                    println!("Level {} {} after {} steps", n, outcome, steps);
+                    finished = Some(Instant::now());
+                }
+            }
+
+            match finished {
+                Some(since) => gfx.transition_display(
+                    stack.get_board(),
+                    if stack.get_board().is_solved() {
+                        Palette::Success
+                    } else {
+                        Palette::Fail
+                    },
+                    since.elapsed().as_secs_f64() / TRANSITION.as_secs_f64(),
+                ),
+                None => gfx.highlight_display(stack.get_board(), &stack.branch_cells()),
+            }
+            gfx.render();
+        }
+
+        if let Event::WindowEvent { event, .. } = &event {
+            match event {
+                WindowEvent::CloseRequested => {
+                    *control_flow = ControlFlow::Exit;
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } => {
+                    match key {
+                        VirtualKeyCode::Space => paused = !paused,
+                        VirtualKeyCode::Right => single_step = true,
+                        VirtualKeyCode::Up => steps_per_frame = steps_per_frame.saturating_mul(2),
+                        VirtualKeyCode::Down => steps_per_frame = (steps_per_frame / 2).max(1),
+                        VirtualKeyCode::C => gfx.scheme = gfx.scheme.next(),
+                        VirtualKeyCode::G => gfx.glyphs = !gfx.glyphs,
+                        VirtualKeyCode::R | VirtualKeyCode::N => {
+                            if *key == VirtualKeyCode::N {
+                                n = n % NUM_PUZZLES + 1;
+                            }
+                            stack = new_stack(n);
+                            steps = 0;
+                            finished = None;
+                        }
+                        _ => {}
+                    }
+                    set_title(&gfx, n, steps_per_frame, paused);
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+fn set_title(gfx: &Gfx, n: u32, steps_per_frame: u32, paused: bool) {
+    gfx.window.set_title(&format!(
+        "Watching level {} - {} steps/frame{}",
+        n,
+        steps_per_frame,
+        if paused { " (paused)" } else { "" }
+    ));
+}
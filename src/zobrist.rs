@@ -0,0 +1,91 @@
+// Zobrist hashing of solver states, and a table of states already shown to have no solution.
+//
+// Every (cell, colour) pair and every cell as the tip of a flow gets a random key. A state hashes
+// to the xor of the keys of its filled cells and of its flows' tips, so the solver can update the
+// hash with a few xors per move instead of rehashing the board. The keys depend only on the board's
+// size and number of colours, so hashes from different searches can be compared.
+
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::board::Board;
+use crate::solver::Coord;
+
+const SEED: u64 = 0x466c_6f77; // Fixed so that runs are repeatable
+const TABLE_BITS: u32 = 18; // 2^18 slots, 2MB
+
+#[derive(Debug)]
+pub struct Zobrist {
+    cols: usize,
+    colors: usize,
+    cells: Vec<u64>, // cell * colors + colour
+    tips: Vec<u64>,  // cell
+}
+
+impl Zobrist {
+    pub fn new(rows: usize, cols: usize, colors: usize) -> Zobrist {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        Zobrist {
+            cols,
+            colors,
+            cells: (0..rows * cols * colors).map(|_| rng.random()).collect(),
+            tips: (0..rows * cols).map(|_| rng.random()).collect(),
+        }
+    }
+
+    pub fn cell(&self, c: Coord, color: u8) -> u64 {
+        self.cells[(c.0 * self.cols + c.1) * self.colors + color as usize]
+    }
+
+    pub fn tip(&self, c: Coord) -> u64 {
+        self.tips[c.0 * self.cols + c.1]
+    }
+
+    ///Hash of a board with the given tips, from scratch
+    pub fn hash(&self, board: &Board, tips: &[Coord]) -> u64 {
+        let cells = (0..board.len())
+            .filter(|i| !board[*i].is_empty())
+            .map(|i| self.cell(board.inverse_ind(i), board[i].color()));
+        cells
+            .chain(tips.iter().map(|t| self.tip(*t)))
+            .fold(0, |hash, key| hash ^ key)
+    }
+}
+
+///Fixed-size table of hashes of states with no solution, one for the whole process. States never
+///repeat within one search: a flow can't fill a cell next to its own path, so sibling subtrees
+///never meet. A later search of the same puzzle, such as the game's check after each drag, can
+///reach states an earlier one has already searched to the end, so every search shares the table.
+///Each hash has one slot, and a new entry overwrites whatever was there, so memory stays bounded.
+pub struct Table {
+    slots: Vec<AtomicU64>,
+}
+
+static TABLE: LazyLock<Table> = LazyLock::new(|| Table {
+    slots: (0..1 << TABLE_BITS).map(|_| AtomicU64::new(0)).collect(),
+});
+
+impl Table {
+    pub fn shared() -> &'static Table {
+        &TABLE
+    }
+
+    // 0 marks an empty slot, so a hash of 0 is stored as 1
+    fn slot(hash: u64) -> (usize, u64) {
+        let hash = hash.max(1);
+        ((hash >> (64 - TABLE_BITS)) as usize, hash)
+    }
+
+    pub fn contains(&self, hash: u64) -> bool {
+        let (i, hash) = Self::slot(hash);
+        self.slots[i].load(Ordering::Relaxed) == hash
+    }
+
+    pub fn insert(&self, hash: u64) {
+        let (i, hash) = Self::slot(hash);
+        self.slots[i].store(hash, Ordering::Relaxed);
+    }
+}